@@ -0,0 +1,158 @@
+// Standard Library
+use std::error::Error;
+use std::path::PathBuf;
+
+// External Library
+use encoding_rs::Encoding;
+use serde_json::{Map, Value};
+
+use crate::brewing_demand::{BrewerInput, BrewingDemand};
+use crate::csv_brewer::{self, CsvConfig};
+use crate::data_brewer_micro;
+use crate::filter_expr;
+use crate::input_matcher::{self, MatchMode};
+use crate::json_brewer::{self, JsonConfig, RecordWriter};
+
+/// Input file extensions the filter brewer reads: CSV/TSV and JSON.
+const FILTER_EXTENSIONS: &[&str] = &[".csv", ".tsv", ".txt", ".json", ".jsonl", ".ndjson"];
+
+/// Which brewer a demand's brewing arguments ask for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrewerKind {
+    /// The template's sample brewer, run once per `sample_key` argument
+    Sample,
+    /// The CSV/TSV brewer, selected by any `csv_*` argument
+    Csv,
+    /// The JSON/JSON Lines brewer, selected by any `json_*` argument
+    Json,
+    /// Keeps the CSV rows or JSON records matching the `filter` argument
+    Filter,
+}
+
+impl BrewerKind {
+    pub fn for_demand(demand: &BrewingDemand) -> Self {
+        if csv_brewer::is_requested(&demand.arguments) {
+            BrewerKind::Csv
+        } else if json_brewer::is_requested(&demand.arguments) {
+            BrewerKind::Json
+        } else if filter_expr::is_requested(&demand.arguments) {
+            BrewerKind::Filter
+        } else {
+            BrewerKind::Sample
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BrewerKind::Sample => "sample",
+            BrewerKind::Csv => "csv",
+            BrewerKind::Json => "json",
+            BrewerKind::Filter => "filter",
+        }
+    }
+
+    /// Input file extensions the brewer reads.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            BrewerKind::Sample => data_brewer_micro::EXTENSIONS,
+            BrewerKind::Csv => csv_brewer::EXTENSIONS,
+            BrewerKind::Json => json_brewer::EXTENSIONS,
+            BrewerKind::Filter => FILTER_EXTENSIONS,
+        }
+    }
+}
+
+/// A brewer configured for one input distribution.
+#[derive(Clone, Debug)]
+pub enum Brewer {
+    Sample(Vec<Map<String, Value>>),
+    Csv(CsvConfig),
+    Json(JsonConfig),
+}
+
+impl Brewer {
+    pub fn new(
+        kind: BrewerKind,
+        demand: &BrewingDemand,
+        input: &BrewerInput,
+        encoding_format: Option<&str>,
+        pattern: &str,
+    ) -> Result<Self, String> {
+        match kind {
+            BrewerKind::Sample => Ok(Brewer::Sample(demand.sample_arguments())),
+            BrewerKind::Csv => {
+                CsvConfig::new(&demand.arguments, input.dataset.structure_info.as_ref(), encoding_format, pattern, demand.time_zone)
+                    .map(Brewer::Csv)
+            }
+            BrewerKind::Json => {
+                JsonConfig::new(&demand.arguments, input.dataset.structure_info.as_ref(), demand.output_pattern(), demand.time_zone)
+                    .map(Brewer::Json)
+            }
+            BrewerKind::Filter => {
+                let structure = input.dataset.structure_info.as_ref();
+                if json_brewer::is_json(pattern) {
+                    JsonConfig::for_filter(&demand.arguments, structure, demand.output_pattern(), demand.time_zone)
+                        .map(Brewer::Json)
+                } else {
+                    CsvConfig::for_filter(&demand.arguments, structure, encoding_format, pattern, demand.time_zone)
+                        .map(Brewer::Csv)
+                }
+            }
+        }
+    }
+
+    /// Brews the files matched for one slot and joins the results in match
+    /// order. The sample brewer reads them one at a time or concatenated,
+    /// per `match_mode`; the CSV brewer always reads each file with its own
+    /// header and writes a single header, and the JSON brewer reads each file
+    /// as its own stream of records and writes them all as one output.
+    pub fn brew(
+        &self,
+        data_set_paths: &[PathBuf],
+        match_mode: MatchMode,
+        charset: Option<&'static Encoding>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut brewed = String::new();
+        match self {
+            Brewer::Sample(brewing_arguments) => {
+                let groups: Vec<&[PathBuf]> = match match_mode {
+                    MatchMode::Each => data_set_paths.chunks(1).collect(),
+                    MatchMode::Group => vec![data_set_paths],
+                };
+                for group in groups {
+                    let mut brewed_data: String = String::new();
+                    for arg in brewing_arguments {
+                        let mut reader = input_matcher::open_group(group, charset)?;
+                        brewed_data = data_brewer_micro::data_brewer_sample(&mut reader, arg.clone()).map_err(|e| {
+                            eprintln!("Error brewing data: {}", e);
+                            Box::<dyn Error>::from("Error: Unable to brew data")
+                        })?;
+                    }
+                    brewed.push_str(&brewed_data);
+                }
+            }
+            Brewer::Csv(config) => {
+                for (i, path) in data_set_paths.iter().enumerate() {
+                    let mut reader = input_matcher::open_group(std::slice::from_ref(path), charset)?;
+                    let brewed_data = csv_brewer::brew(&mut reader, config, i == 0).map_err(|e| {
+                        eprintln!("Error brewing data {}: {}", path.display(), e);
+                        Box::<dyn Error>::from("Error: Unable to brew data")
+                    })?;
+                    brewed.push_str(&brewed_data);
+                }
+            }
+            Brewer::Json(config) => {
+                let mut writer = RecordWriter::new(config);
+                for path in data_set_paths {
+                    let mut reader = input_matcher::open_group(std::slice::from_ref(path), charset)?;
+                    json_brewer::brew(&mut reader, config, &mut writer).map_err(|e| {
+                        eprintln!("Error brewing data {}: {}", path.display(), e);
+                        Box::<dyn Error>::from("Error: Unable to brew data")
+                    })?;
+                }
+                brewed.push_str(&writer.finish()?);
+            }
+        }
+        Ok(brewed)
+    }
+}
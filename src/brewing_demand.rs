@@ -0,0 +1,496 @@
+// Standard Library
+use std::fmt;
+use std::sync::Arc;
+
+// External Library
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use dbp_schema::dbp_schema::{RealWorldDataset, RealWorldDataStoringInfo};
+use json_ld_utils::{
+    DBP_BASE_URL, DBP_BREWER_INFO, DBP_BREWER_INPUT, DBP_BREWER_OUTPUT_STORE, DBP_BREWING_ARGUMENT,
+    DBP_KEY, DBP_PATTERN, DBP_TIME_PERIOD_END, DBP_TIME_PERIOD_START, SC_DATASET, SC_DISTRIBUTION,
+    SC_NAME, SC_VALUE,
+};
+use serde_json::{Map, Value};
+
+use crate::json_ld_loader::{self, ParseContext, ParseMode};
+use crate::ref_resolver::RefResolver;
+use crate::text_encoding;
+use crate::time_parser;
+use crate::utils;
+
+const DBP_TIME_ZONE: &str = "dbp:timeZone";
+
+/// An entry of a demand's `dbp:brewerInput`: the dataset itself and every
+/// storage location listed in its `schema:distribution`.
+#[derive(Clone, Debug)]
+pub struct BrewerInput {
+    pub dataset: RealWorldDataset,
+    pub distributions: Vec<Distribution>,
+}
+
+/// One storage location of an input dataset.
+#[derive(Clone, Debug)]
+pub struct Distribution {
+    pub info: RealWorldDataStoringInfo,
+    /// `schema:encodingFormat`, e.g. `text/csv`.
+    pub encoding_format: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BrewerInfo {
+    pub id: Option<String>,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct BrewingArgument {
+    pub key: String,
+    pub value: String,
+}
+
+/// A validated `RealWorldDataBrewingDemand`.
+#[derive(Clone, Debug)]
+pub struct BrewingDemand {
+    pub id: Option<String>,
+    pub brewer_info: BrewerInfo,
+    pub inputs: Vec<BrewerInput>,
+    pub output_store: RealWorldDataStoringInfo,
+    /// Omitted bounds are taken from the slots present in the inputs.
+    pub time_period_start: Option<DateTime<FixedOffset>>,
+    pub time_period_end: Option<DateTime<FixedOffset>>,
+    /// `dbp:timeZone`, or `--timezone` for demands without one: the zone
+    /// patterns are expanded, slots stepped and times without an offset read in.
+    pub time_zone: Tz,
+    pub arguments: Vec<BrewingArgument>,
+    /// The demand as loaded, kept for provenance output.
+    pub raw: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    /// JSON path of the offending field, e.g. `$.dbp:brewerOutputStore.dbp:baseUrl`.
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem found in a demand, reported together so that users can fix
+/// them in one pass.
+#[derive(Clone, Default)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    fn push(&mut self, path: &str, message: &str) {
+        self.0.push(ValidationError { path: path.to_string(), message: message.to_string() });
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem(s) in brewing demand:", self.0.len())?;
+        for e in &self.0 {
+            write!(f, "\n  {}: {}", e.path, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+// `main` prints returned errors with `{:?}`; keep that output readable.
+impl fmt::Debug for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+fn child(path: &str, key: &str) -> String {
+    format!("{}.{}", path, key)
+}
+
+fn index(path: &str, i: usize) -> String {
+    format!("{}[{}]", path, i)
+}
+
+/// Reads a required string field, recording an error when it is missing or
+/// not a string.
+fn required_str<'a>(
+    obj: &'a Value,
+    key: &str,
+    path: &str,
+    errors: &mut ValidationErrors,
+) -> Option<&'a str> {
+    match obj.get(key) {
+        None | Some(Value::Null) => {
+            errors.push(&child(path, key), "is missing");
+            None
+        }
+        Some(Value::String(s)) => Some(s.as_str()),
+        Some(_) => {
+            errors.push(&child(path, key), "must be a string");
+            None
+        }
+    }
+}
+
+fn optional_time(
+    demand: &Value,
+    key: &str,
+    time_zone: Tz,
+    errors: &mut ValidationErrors,
+) -> Option<DateTime<FixedOffset>> {
+    match demand.get(key) {
+        None | Some(Value::Null) => None,
+        Some(v) => match time_parser::parse_time(v, time_zone) {
+            Ok(dt) => Some(dt),
+            Err(e) => {
+                errors.push(&child("$", key), &e);
+                None
+            }
+        },
+    }
+}
+
+async fn storing_info(
+    ctx: &ParseContext,
+    d_json: &Value,
+    path: &str,
+    errors: &mut ValidationErrors,
+) -> Option<Distribution> {
+    if !d_json.is_object() {
+        errors.push(path, "must be an object");
+        return None;
+    }
+    let d_json = match d_json.get("@ref").and_then(|v| v.as_str()) {
+        Some(ref_url) => match ctx.resolver.fetch(ref_url).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                errors.push(path, &format!("@ref could not be resolved: {}", e));
+                return None;
+            }
+        },
+        None => d_json.clone(),
+    };
+
+    let error_count = errors.0.len();
+    required_str(&d_json, DBP_BASE_URL, path, errors);
+    if let Some(pattern) = required_str(&d_json, DBP_PATTERN, path, errors) {
+        if utils::extract_minimum_unit(pattern).is_none() {
+            errors.push(&child(path, DBP_PATTERN), "has no time placeholder (%Y, %m, %d, %H, %M, %S)");
+        }
+    }
+    let encoding_format = match d_json.get("schema:encodingFormat") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(format)) => {
+            if let Some(Err(e)) = text_encoding::charset(format).map(text_encoding::lookup) {
+                errors.push(&child(path, "schema:encodingFormat"), &e);
+            }
+            Some(format.clone())
+        }
+        Some(_) => {
+            errors.push(&child(path, "schema:encodingFormat"), "must be a string");
+            None
+        }
+    };
+    if errors.0.len() > error_count {
+        return None;
+    }
+    // `d_json` is already resolved; parsing it must not follow `@ref` again.
+    Some(Distribution { info: json_ld_loader::distribution_parser(ctx, &d_json, path), encoding_format })
+}
+
+impl BrewingDemand {
+    /// Builds a demand from loaded JSON-LD, collecting every missing or
+    /// malformed field instead of stopping at the first one. Type mismatches
+    /// in optional metadata are errors in strict mode and warnings otherwise.
+    pub async fn from_json_ld(
+        raw: &Map<String, Value>,
+        mode: ParseMode,
+        resolver: Arc<RefResolver>,
+        max_depth: usize,
+        default_time_zone: Tz,
+    ) -> Result<BrewingDemand, ValidationErrors> {
+        let demand = Value::Object(raw.clone());
+        let mut errors = ValidationErrors::default();
+
+        // Time zone, read first since every time without an offset is local
+        // to it.
+        let time_zone_path = child("$", DBP_TIME_ZONE);
+        let time_zone = match demand.get(DBP_TIME_ZONE) {
+            None | Some(Value::Null) => default_time_zone,
+            Some(Value::String(name)) => name.parse::<Tz>().unwrap_or_else(|_| {
+                errors.push(&time_zone_path, &format!("unknown IANA time zone {:?}", name));
+                default_time_zone
+            }),
+            Some(_) => {
+                errors.push(&time_zone_path, "must be a string");
+                default_time_zone
+            }
+        };
+        let ctx = ParseContext::new(resolver, max_depth, time_zone);
+
+        // Brewer info
+        let brewer_info = match demand.get(DBP_BREWER_INFO) {
+            Some(info) if info.is_object() => {
+                let path = child("$", DBP_BREWER_INFO);
+                required_str(info, SC_NAME, &path, &mut errors).map(|name| BrewerInfo {
+                    id: info.get("@id").and_then(|v| v.as_str()).map(String::from),
+                    name: name.to_string(),
+                })
+            }
+            Some(_) => {
+                errors.push(&child("$", DBP_BREWER_INFO), "must be an object");
+                None
+            }
+            None => {
+                errors.push(&child("$", DBP_BREWER_INFO), "is missing");
+                None
+            }
+        };
+
+        // Output store
+        let output_path = child("$", DBP_BREWER_OUTPUT_STORE);
+        let output_store = match demand.get(DBP_BREWER_OUTPUT_STORE) {
+            Some(store) => storing_info(&ctx, store, &output_path, &mut errors).await.map(|d| d.info),
+            None => {
+                errors.push(&output_path, "is missing");
+                None
+            }
+        };
+
+        // Inputs
+        let inputs_path = child("$", DBP_BREWER_INPUT);
+        let mut inputs = Vec::new();
+        match demand.get(DBP_BREWER_INPUT) {
+            Some(Value::Array(inputs_json)) if !inputs_json.is_empty() => {
+                for (i, input_json) in inputs_json.iter().enumerate() {
+                    let ds_path = child(&index(&inputs_path, i), SC_DATASET);
+                    let ds_json = match input_json.get(SC_DATASET) {
+                        Some(ds_json) if ds_json.is_object() => ds_json,
+                        Some(_) => {
+                            errors.push(&ds_path, "must be an object");
+                            continue;
+                        }
+                        None => {
+                            errors.push(&ds_path, "is missing");
+                            continue;
+                        }
+                    };
+                    let d_path = child(&ds_path, SC_DISTRIBUTION);
+                    let mut distributions = Vec::new();
+                    match ds_json.get(SC_DISTRIBUTION) {
+                        Some(Value::Array(ds)) if !ds.is_empty() => {
+                            for (j, d_json) in ds.iter().enumerate() {
+                                distributions.extend(storing_info(&ctx, d_json, &index(&d_path, j), &mut errors).await);
+                            }
+                        }
+                        Some(Value::Array(_)) => errors.push(&d_path, "must not be empty"),
+                        Some(d_json) => distributions.extend(storing_info(&ctx, d_json, &d_path, &mut errors).await),
+                        None => errors.push(&d_path, "is missing"),
+                    }
+                    inputs.push(BrewerInput {
+                        dataset: json_ld_loader::dataset_getter(&ctx, ds_json, &ds_path).await,
+                        distributions,
+                    });
+                }
+            }
+            Some(Value::Array(_)) => errors.push(&inputs_path, "must not be empty"),
+            Some(_) => errors.push(&inputs_path, "must be an array"),
+            None => errors.push(&inputs_path, "is missing"),
+        }
+
+        // Time period
+        // Either bound may be omitted to brew everything present in the inputs.
+        let time_period_start = optional_time(&demand, DBP_TIME_PERIOD_START, time_zone, &mut errors);
+        let time_period_end = optional_time(&demand, DBP_TIME_PERIOD_END, time_zone, &mut errors);
+        if let (Some(start), Some(end)) = (time_period_start, time_period_end) {
+            if start > end {
+                errors.push(
+                    &child("$", DBP_TIME_PERIOD_END),
+                    &format!("is before {} ({} > {})", DBP_TIME_PERIOD_START, start, end),
+                );
+            }
+        }
+
+
+        // Brewing arguments
+        let args_path = child("$", DBP_BREWING_ARGUMENT);
+        let mut arguments = Vec::new();
+        match demand.get(DBP_BREWING_ARGUMENT) {
+            Some(Value::Array(args_json)) => {
+                for (i, arg_json) in args_json.iter().enumerate() {
+                    let arg_path = index(&args_path, i);
+                    let key = required_str(arg_json, DBP_KEY, &arg_path, &mut errors);
+                    let value = match arg_json.get(SC_VALUE) {
+                        Some(Value::String(s)) => Some(s.clone()),
+                        Some(v @ (Value::Number(_) | Value::Bool(_))) => Some(v.to_string()),
+                        Some(Value::Null) | None => {
+                            errors.push(&child(&arg_path, SC_VALUE), "is missing");
+                            None
+                        }
+                        Some(_) => {
+                            errors.push(&child(&arg_path, SC_VALUE), "must be a string, number or boolean");
+                            None
+                        }
+                    };
+                    if let (Some(key), Some(value)) = (key, value) {
+                        arguments.push(BrewingArgument { key: key.to_string(), value });
+                    }
+                }
+            }
+            Some(_) => errors.push(&args_path, "must be an array"),
+            None => errors.push(&args_path, "is missing"),
+        }
+
+        for d in ctx.take_errors() {
+            errors.push(&d.path, &d.message);
+        }
+        for d in ctx.take_diagnostics() {
+            match mode {
+                ParseMode::Strict => errors.push(&d.path, &d.message),
+                ParseMode::Lenient => warn!("{}: {}", d.path, d.message),
+            }
+        }
+        if !errors.0.is_empty() {
+            return Err(errors);
+        }
+        match (brewer_info, output_store) {
+            (Some(brewer_info), Some(output_store)) => {
+                Ok(BrewingDemand {
+                    id: raw.get("@id").and_then(|v| v.as_str()).map(String::from),
+                    brewer_info,
+                    inputs,
+                    output_store,
+                    time_period_start,
+                    time_period_end,
+                    time_zone,
+                    arguments,
+                    raw: raw.clone(),
+                })
+            }
+            (brewer_info, _) => {
+                // Every known way of losing either field records an error
+                // above; should one slip through, still fail with a path
+                // rather than bring the worker down.
+                let missing = if brewer_info.is_none() { DBP_BREWER_INFO } else { DBP_BREWER_OUTPUT_STORE };
+                errors.push(&child("$", missing), "could not be read");
+                Err(errors)
+            }
+        }
+    }
+
+    pub fn output_base_url(&self) -> &str {
+        self.output_store.base_url.as_deref().unwrap_or_default()
+    }
+
+    pub fn output_pattern(&self) -> &str {
+        self.output_store.pattern.as_deref().unwrap_or_default()
+    }
+
+    /// Arguments handed to the sample brewer, one map per `sample_key` entry.
+    pub fn sample_arguments(&self) -> Vec<Map<String, Value>> {
+        self.arguments
+            .iter()
+            .filter(|arg| arg.key == "sample_key")
+            .map(|arg| {
+                let mut map = Map::new();
+                map.insert(arg.key.clone(), Value::String(arg.value.clone()));
+                map
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::json_ld_loader::DEFAULT_PROVENANCE_DEPTH;
+
+    async fn parse(raw: Value, mode: ParseMode) -> Result<BrewingDemand, ValidationErrors> {
+        let Value::Object(raw) = raw else { unreachable!() };
+        let resolver = Arc::new(RefResolver::new(None, None, true));
+        BrewingDemand::from_json_ld(&raw, mode, resolver, DEFAULT_PROVENANCE_DEPTH, Tz::UTC).await
+    }
+
+    fn paths(errors: &ValidationErrors) -> Vec<&str> {
+        errors.0.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn reports_every_missing_field_at_once() {
+        let errors = parse(json!({}), ParseMode::Lenient).await.unwrap_err();
+        let errors: Vec<(&str, &str)> = errors.0.iter().map(|e| (e.path.as_str(), e.message.as_str())).collect();
+        assert_eq!(
+            errors,
+            [
+                ("$.dbp:brewerInfo", "is missing"),
+                ("$.dbp:brewerOutputStore", "is missing"),
+                ("$.dbp:brewerInput", "is missing"),
+                ("$.dbp:brewingArgument", "is missing"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn locates_nested_problems_by_path() {
+        let raw = json!({
+            "dbp:timeZone": "Mars/Olympus_Mons",
+            "dbp:brewerInfo": {},
+            "dbp:brewerInput": [
+                { "schema:dataset": {
+                    "schema:name": "a",
+                    "schema:distribution": [
+                        { "dbp:baseUrl": "file:///tmp/in/", "dbp:pattern": "%Y.csv" },
+                        { "dbp:baseUrl": 1, "dbp:pattern": "static.csv", "schema:encodingFormat": "text/csv; charset=nope" },
+                    ],
+                } },
+                { "schema:dataset": "b" },
+                { "schema:dataset": { "schema:distribution": [] } },
+            ],
+            "dbp:brewerOutputStore": { "dbp:baseUrl": "file:///tmp/out/" },
+            "dbp:timePeriodStart": "2023-09-02T00:00:00Z",
+            "dbp:timePeriodEnd": "2023-09-01T00:00:00Z",
+            "dbp:brewingArgument": [{ "dbp:key": "k" }, { "dbp:key": "n", "schema:value": 1 }, { "schema:value": [] }],
+        });
+        let errors = parse(raw, ParseMode::Lenient).await.unwrap_err();
+        assert_eq!(
+            paths(&errors),
+            [
+                "$.dbp:timeZone",
+                "$.dbp:brewerInfo.schema:name",
+                "$.dbp:brewerOutputStore.dbp:pattern",
+                "$.dbp:brewerInput[0].schema:dataset.schema:distribution[1].dbp:baseUrl",
+                "$.dbp:brewerInput[0].schema:dataset.schema:distribution[1].dbp:pattern",
+                "$.dbp:brewerInput[0].schema:dataset.schema:distribution[1].schema:encodingFormat",
+                "$.dbp:brewerInput[1].schema:dataset",
+                "$.dbp:brewerInput[2].schema:dataset.schema:distribution",
+                "$.dbp:timePeriodEnd",
+                "$.dbp:brewingArgument[0].schema:value",
+                "$.dbp:brewingArgument[2].dbp:key",
+                "$.dbp:brewingArgument[2].schema:value",
+            ]
+        );
+        let text = errors.to_string();
+        assert!(text.starts_with("12 problem(s) in brewing demand:\n  $.dbp:timeZone: unknown IANA time zone"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn metadata_mismatches_fail_only_in_strict_mode() {
+        let raw = json!({
+            "dbp:brewerInfo": { "schema:name": "dbpBrewerTemplate" },
+            "dbp:brewerInput": [{ "schema:dataset": {
+                "schema:name": 1,
+                "schema:distribution": { "dbp:baseUrl": "file:///tmp/in/", "dbp:pattern": "%Y.csv" },
+            } }],
+            "dbp:brewerOutputStore": { "dbp:baseUrl": "file:///tmp/out/", "dbp:pattern": "%Y.csv" },
+            "dbp:brewingArgument": [{ "dbp:key": "n", "schema:value": 1 }],
+        });
+        let demand = parse(raw.clone(), ParseMode::Lenient).await.unwrap();
+        assert_eq!(demand.inputs[0].dataset.name, None);
+        assert_eq!((demand.arguments[0].key.as_str(), demand.arguments[0].value.as_str()), ("n", "1"));
+        let errors = parse(raw, ParseMode::Strict).await.unwrap_err();
+        assert_eq!(paths(&errors), ["$.dbp:brewerInput[0].schema:dataset.schema:name"]);
+    }
+}
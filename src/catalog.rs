@@ -0,0 +1,209 @@
+// Standard Library
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// External Library
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ref_resolver::{url_file_name, RefResolver};
+
+pub const CATALOG_INDEX_FILE_NAME: &str = "catalog.json";
+const DOCUMENTS_DIR_NAME: &str = "documents";
+
+/// `catalog.json`: maps document URLs and `@id`s to files relative to the
+/// catalog directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CatalogIndex {
+    pub documents: BTreeMap<String, String>,
+}
+
+enum CatalogEntry {
+    File(PathBuf),
+    Document(Value),
+}
+
+/// A local set of JSON-LD documents used to resolve `@ref`s and remote
+/// `@context`s without the network. Either a directory with a `catalog.json`
+/// index, or a single bundle file mapping URLs / `@id`s to documents.
+pub struct Catalog {
+    entries: HashMap<String, CatalogEntry>,
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = HashMap::new();
+        if path.is_dir() {
+            let index_path = path.join(CATALOG_INDEX_FILE_NAME);
+            let text = fs::read_to_string(&index_path)
+                .map_err(|e| format!("Unable to read catalog index {}: {}", index_path.display(), e))?;
+            let index: CatalogIndex = serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid catalog index {}: {}", index_path.display(), e))?;
+            for (key, file) in index.documents {
+                entries.insert(key, CatalogEntry::File(path.join(file)));
+            }
+        } else {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Unable to read catalog bundle {}: {}", path.display(), e))?;
+            let bundle: HashMap<String, Value> = serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid catalog bundle {}: {}", path.display(), e))?;
+            for (key, document) in bundle {
+                entries.insert(key, CatalogEntry::Document(document));
+            }
+        }
+        info!("Loaded catalog {} ({} entries)", path.display(), entries.len());
+        Ok(Catalog { entries })
+    }
+
+    /// Looks up a URL or `@id`. `None` means the catalog does not know it.
+    pub fn get(&self, key: &str) -> Option<Result<Value, String>> {
+        let entry = self.entries.get(key).or_else(|| self.entries.get(key.trim_end_matches('#')))?;
+        Some(match entry {
+            CatalogEntry::Document(document) => Ok(document.clone()),
+            CatalogEntry::File(path) => fs::read_to_string(path)
+                .map_err(|e| format!("Unable to read catalog entry {} for {}: {}", path.display(), key, e))
+                .and_then(|text| {
+                    serde_json::from_str(&text).map_err(|e| format!("{} is not JSON: {}", path.display(), e))
+                }),
+        })
+    }
+}
+
+/// URLs a document depends on: every `@ref` and every remote `@context`.
+fn linked_urls(value: &Value, urls: &mut Vec<String>) {
+    match value {
+        Value::Object(obj) => {
+            for (key, v) in obj {
+                match (key.as_str(), v) {
+                    ("@ref", Value::String(url)) => urls.push(url.clone()),
+                    ("@context", Value::String(url)) if url.starts_with("http") => urls.push(url.clone()),
+                    ("@context", Value::Array(contexts)) => {
+                        for context in contexts {
+                            match context {
+                                Value::String(url) if url.starts_with("http") => urls.push(url.clone()),
+                                _ => linked_urls(context, urls),
+                            }
+                        }
+                    }
+                    _ => linked_urls(v, urls),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| linked_urls(v, urls)),
+        _ => {}
+    }
+}
+
+/// Crawls a demand once online and writes every document it references into
+/// a catalog directory usable with `--catalog`. Documents are fetched as
+/// `@ref`s are, and one that cannot be fetched is skipped with a warning.
+/// Returns the number of documents written.
+pub async fn snapshot(demand: &str, output_dir: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let documents_dir = output_dir.join(DOCUMENTS_DIR_NAME);
+    fs::create_dir_all(&documents_dir)?;
+    let index_path = output_dir.join(CATALOG_INDEX_FILE_NAME);
+    let mut index = match fs::read_to_string(&index_path) {
+        Ok(text) => serde_json::from_str::<CatalogIndex>(&text)?,
+        Err(_) => CatalogIndex::default(),
+    };
+
+    let mut queue = VecDeque::new();
+    if demand.starts_with("http") {
+        queue.push_back(demand.to_string());
+    } else {
+        let mut urls = Vec::new();
+        linked_urls(&serde_json::from_str(demand)?, &mut urls);
+        queue.extend(urls);
+    }
+
+    // Nothing is cached, so that the snapshot holds the documents as served now.
+    let resolver = RefResolver::new(None, None, false);
+    let mut visited = HashSet::new();
+    let mut written = 0;
+    while let Some(url) = queue.pop_front() {
+        if !visited.insert(url.clone()) {
+            continue;
+        }
+        info!("snapshot: {}", url);
+        let document = match resolver.fetch_raw(&url).await {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("snapshot: skipping {}: {}", url, e);
+                continue;
+            }
+        };
+
+        let file = format!("{}/{}", DOCUMENTS_DIR_NAME, url_file_name(&url));
+        fs::write(output_dir.join(&file), serde_json::to_string_pretty(&document)?)?;
+        index.documents.insert(url.clone(), file.clone());
+        if let Some(id) = document.get("@id").and_then(|v| v.as_str()) {
+            index.documents.entry(id.to_string()).or_insert(file);
+        }
+        written += 1;
+
+        let mut urls = Vec::new();
+        linked_urls(&document, &mut urls);
+        queue.extend(urls.into_iter().filter(|u| !visited.contains(u)));
+    }
+
+    fs::write(&index_path, serde_json::to_string_pretty(&index)?)?;
+    info!("Wrote catalog {} ({} documents)", index_path.display(), index.documents.len());
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn snapshots_what_can_be_fetched() {
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        // Served as HTML, with the JSON-LD linked as an alternate.
+        Mock::given(method("GET"))
+            .and(path("/demand"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "text/html")
+                    .insert_header("Link", "</demand.jsonld>; rel=\"alternate\"; type=\"application/ld+json\"")
+                    .set_body_string("<html></html>"),
+            )
+            .mount(&server)
+            .await;
+        let demand = json!({
+            "@id": format!("{}/demands/1", uri),
+            "dbp:brewerOutputStore": { "@ref": format!("{}/store", uri) },
+            "dbp:brewerInput": [{ "@ref": format!("{}/missing", uri) }],
+        });
+        Mock::given(method("GET"))
+            .and(path("/demand.jsonld"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(demand.clone()))
+            .mount(&server)
+            .await;
+        let store = json!({ "dbp:baseUrl": "file:///out/", "dbp:pattern": "%Y.csv" });
+        Mock::given(method("GET"))
+            .and(path("/store"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(store.clone()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET")).and(path("/missing")).respond_with(ResponseTemplate::new(404)).mount(&server).await;
+
+        let dir = std::env::temp_dir().join(format!("dbp-catalog-{}", std::process::id()));
+        let written = snapshot(&format!("{}/demand", uri), &dir).await;
+        let catalog = Catalog::open(&dir).unwrap();
+        // Entries are read lazily, so before the directory goes.
+        let get = |url: &str| catalog.get(&format!("{}{}", uri, url)).map(Result::unwrap);
+        let (by_url, by_id, store_found, missing) = (get("/demand"), get("/demands/1"), get("/store"), get("/missing"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written.unwrap(), 2);
+        assert_eq!(by_url, Some(demand.clone()));
+        assert_eq!(by_id, Some(demand));
+        assert_eq!(store_found, Some(store));
+        assert_eq!(missing, None);
+    }
+}
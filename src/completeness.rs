@@ -0,0 +1,255 @@
+// Standard Library
+use std::collections::BTreeSet;
+use std::fmt;
+
+// External Library
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::brewing_demand::BrewingDemand;
+use crate::inventory::{self, Inventory};
+use crate::placeholders::Values;
+use crate::plan::BrewPlan;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum CompletenessFormat {
+    /// Do not report completeness
+    #[default]
+    Off,
+    /// Print a table to stdout
+    Table,
+    /// Print JSON to stdout
+    Json,
+}
+
+/// Consecutive expected slots with no file.
+#[derive(Clone, Debug, Serialize)]
+pub struct MissingRange {
+    pub start: String,
+    pub end: String,
+    pub slots: usize,
+}
+
+/// How much of the requested period one store holds.
+#[derive(Clone, Debug, Serialize)]
+pub struct StoreCompleteness {
+    pub name: String,
+    pub role: &'static str,
+    pub base_url: String,
+    pub pattern: String,
+    pub expected: usize,
+    pub present: usize,
+    pub missing: Vec<MissingRange>,
+    pub empty_files: Vec<String>,
+    /// Why the store could not be listed, in which case nothing is present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl StoreCompleteness {
+    fn new(name: &str, role: &'static str, base_url: &str, pattern: &str, values: &Values, plan: &BrewPlan) -> Self {
+        let tz = plan.dt_start.timezone();
+        let mut store = StoreCompleteness {
+            name: name.to_string(),
+            role,
+            base_url: base_url.to_string(),
+            pattern: pattern.to_string(),
+            expected: plan.slots.len(),
+            present: 0,
+            missing: Vec::new(),
+            empty_files: Vec::new(),
+            error: None,
+        };
+        let (present, empty_files) = match Inventory::scan(base_url, pattern, tz, values) {
+            Ok(inventory) => (inventory.slots, inventory.empty_files),
+            Err(e) => {
+                store.error = Some(e);
+                (BTreeSet::new(), Vec::new())
+            }
+        };
+        // Files are named by the boundary of their slot, which a plan slot
+        // need not start on.
+        let slots: Vec<DateTime<Tz>> = plan.slots.iter().map(|&dt| plan.unit.floor(dt)).collect();
+        let expected: BTreeSet<DateTime<Tz>> = slots.iter().copied().collect();
+        store.present = expected.intersection(&present).count();
+        store.missing = inventory::missing_runs(&slots, &present)
+            .into_iter()
+            .map(|(start, end, slots)| MissingRange { start: start.to_rfc3339(), end: end.to_rfc3339(), slots })
+            .collect();
+        store.empty_files = empty_files
+            .into_iter()
+            .filter(|(dt, _)| expected.contains(dt))
+            .map(|(_, path)| path.display().to_string())
+            .collect();
+        store
+    }
+}
+
+/// Expected, present, missing and empty slots of every input dataset's
+/// preferred distribution and of the output store over the planned slots.
+#[derive(Clone, Debug, Serialize)]
+pub struct CompletenessReport {
+    pub stage: &'static str,
+    pub period_start: String,
+    pub period_end: String,
+    pub unit: &'static str,
+    pub stores: Vec<StoreCompleteness>,
+}
+
+impl CompletenessReport {
+    /// Inputs are listed with their known placeholder values; the output
+    /// store with every placeholder matching anything.
+    pub fn new(stage: &'static str, demand: &BrewingDemand, plan: &BrewPlan) -> Self {
+        let mut stores: Vec<StoreCompleteness> = plan
+            .inputs
+            .iter()
+            .filter_map(|input| {
+                let d = input.candidates.first()?;
+                Some(StoreCompleteness::new(&input.dataset, "input", &d.base_url, &d.pattern, &input.values, plan))
+            })
+            .collect();
+        stores.push(StoreCompleteness::new(
+            "output",
+            "output",
+            demand.output_base_url(),
+            &plan.output_pattern,
+            &Values::new(),
+            plan,
+        ));
+        CompletenessReport {
+            stage,
+            period_start: plan.dt_start.to_rfc3339(),
+            period_end: plan.dt_end.to_rfc3339(),
+            unit: plan.unit.as_str(),
+            stores,
+        }
+    }
+
+    pub fn print(&self, format: CompletenessFormat) {
+        match format {
+            CompletenessFormat::Off => {}
+            CompletenessFormat::Table => println!("{}", self),
+            CompletenessFormat::Json => match serde_json::to_string_pretty(self) {
+                Ok(json) => println!("{}", json),
+                Err(e) => error!("Failed to serialize completeness report: {}", e),
+            },
+        }
+    }
+}
+
+impl fmt::Display for CompletenessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "completeness ({}): {} .. {} ({} slots)",
+            self.stage, self.period_start, self.period_end, self.unit
+        )?;
+        let width = self.stores.iter().map(|s| s.name.len() + s.role.len() + 3).max().unwrap_or(0).max(5);
+        write!(f, "{:<width$} {:>8} {:>8} {:>8} {:>6}", "STORE", "EXPECTED", "PRESENT", "MISSING", "EMPTY")?;
+        for store in &self.stores {
+            let label = format!("{} ({})", store.name, store.role);
+            write!(
+                f,
+                "\n{:<width$} {:>8} {:>8} {:>8} {:>6}",
+                label,
+                store.expected,
+                store.present,
+                store.expected - store.present,
+                store.empty_files.len()
+            )?;
+        }
+        for store in &self.stores {
+            if let Some(e) = &store.error {
+                write!(f, "\n{}: {}", store.name, e)?;
+            }
+            for range in &store.missing {
+                write!(f, "\n{}: missing {} .. {} ({} slot(s))", store.name, range.start, range.end, range.slots)?;
+            }
+            for path in &store.empty_files {
+                write!(f, "\n{}: empty {}", store.name, path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::input_matcher::MatchMode;
+    use crate::json_ld_loader::{ParseMode, DEFAULT_PROVENANCE_DEPTH};
+    use crate::plan::OutOfWindowPolicy;
+    use crate::ref_resolver::RefResolver;
+    use crate::slots::{Alignment, IntervalMode, SlotUnit};
+    use crate::text_encoding::TextEncoding;
+    use crate::time_parser;
+
+    const PATTERN: &str = "%Y/%Y-%m-%d.csv";
+
+    async fn demand(base_url: &str) -> BrewingDemand {
+        let raw = json!({
+            "dbp:brewerInfo": { "schema:name": "dbpBrewerTemplate" },
+            "dbp:brewerInput": [{
+                "schema:dataset": {
+                    "schema:name": "sample",
+                    "schema:distribution": { "dbp:baseUrl": base_url, "dbp:pattern": PATTERN },
+                },
+            }],
+            "dbp:brewerOutputStore": { "dbp:baseUrl": base_url, "dbp:pattern": PATTERN },
+            "dbp:brewingArgument": [],
+            "dbp:timeZone": "Asia/Tokyo",
+        });
+        let Value::Object(raw) = raw else { unreachable!() };
+        let resolver = Arc::new(RefResolver::new(None, None, true));
+        BrewingDemand::from_json_ld(&raw, ParseMode::Lenient, resolver, DEFAULT_PROVENANCE_DEPTH, Tz::UTC)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn at(text: &str) -> DateTime<Tz> {
+        time_parser::parse_time(&Value::from(text), Tz::Asia__Tokyo).unwrap().with_timezone(&Tz::Asia__Tokyo)
+    }
+
+    #[tokio::test]
+    async fn slots_off_the_boundary_count_their_files() {
+        let dir = std::env::temp_dir().join(format!("dbp-completeness-{}", std::process::id()));
+        fs::create_dir_all(dir.join("2023")).unwrap();
+        fs::write(dir.join("2023/2023-09-01.csv"), "a\n1\n").unwrap();
+        fs::write(dir.join("2023/2023-09-03.csv"), "").unwrap();
+        let base_url = format!("file://{}/", dir.display());
+        let demand = demand(&base_url).await;
+        let plan = BrewPlan::new(
+            &demand,
+            at("2023-09-01T09:30:00"),
+            at("2023-09-04T09:30:00"),
+            SlotUnit::Day,
+            IntervalMode::Closed,
+            Alignment::None,
+            MatchMode::default(),
+            OutOfWindowPolicy::Skip,
+            &[],
+            None,
+            TextEncoding::default(),
+        );
+
+        let store = StoreCompleteness::new("sample", "input", &base_url, PATTERN, &Values::new(), &plan);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(store.error, None);
+        assert_eq!((store.expected, store.present), (4, 2));
+        let missing: Vec<(String, String, usize)> =
+            store.missing.into_iter().map(|range| (range.start, range.end, range.slots)).collect();
+        let day = |date: &str| at(date).to_rfc3339();
+        assert_eq!(
+            missing,
+            vec![(day("2023-09-02"), day("2023-09-02"), 1), (day("2023-09-04"), day("2023-09-04"), 1)]
+        );
+        assert_eq!(store.empty_files, vec![dir.join("2023/2023-09-03.csv").display().to_string()]);
+    }
+}
@@ -0,0 +1,127 @@
+// Standard Library
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+// External Library
+use clap::ValueEnum;
+
+/// Stream compression of input and output files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Compression {
+    /// Plain, uncompressed files
+    #[default]
+    None,
+    /// gzip (`.gz`)
+    Gzip,
+    /// Zstandard (`.zst`)
+    Zstd,
+    /// bzip2 (`.bz2`)
+    Bzip2,
+    /// xz (`.xz`)
+    Xz,
+}
+
+const EXTENSIONS: [(&str, Compression); 6] = [
+    (".gz", Compression::Gzip),
+    (".gzip", Compression::Gzip),
+    (".zst", Compression::Zstd),
+    (".zstd", Compression::Zstd),
+    (".bz2", Compression::Bzip2),
+    (".xz", Compression::Xz),
+];
+
+const MAGIC_BYTES: [(&[u8], Compression); 4] = [
+    (&[0x1f, 0x8b], Compression::Gzip),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+    (b"BZh", Compression::Bzip2),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Compression::Xz),
+];
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+        }
+    }
+
+    /// Compression named by the extension of a path or pattern.
+    pub fn from_extension(path: &str) -> Compression {
+        EXTENSIONS
+            .iter()
+            .find(|(extension, _)| path.ends_with(extension))
+            .map_or(Compression::None, |&(_, compression)| compression)
+    }
+
+    /// Compression recognised from the first bytes of a file.
+    pub fn from_magic(head: &[u8]) -> Compression {
+        MAGIC_BYTES
+            .iter()
+            .find(|(magic, _)| head.starts_with(magic))
+            .map_or(Compression::None, |&(_, compression)| compression)
+    }
+}
+
+/// A path or pattern without its compression extension, so that
+/// `%Y-%m-%d.csv.gz` is read as `%Y-%m-%d.csv`.
+pub fn strip_extension(path: &str) -> &str {
+    EXTENSIONS
+        .iter()
+        .find_map(|(extension, _)| path.strip_suffix(extension))
+        .unwrap_or(path)
+}
+
+/// Opens a file for reading, decompressing it as a stream when its magic
+/// bytes, or failing that its extension, say it is compressed.
+pub fn open(path: &Path) -> Result<Box<dyn Read>, Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let compression = match Compression::from_magic(reader.fill_buf()?) {
+        Compression::None => Compression::from_extension(&path.to_string_lossy()),
+        compression => compression,
+    };
+    debug!("Reading {} as {}", path.display(), compression.as_str());
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        // Multi-member, as written by `cat a.gz b.gz` or pigz.
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+    })
+}
+
+/// Writes `data` to a file, compressed as given.
+pub fn write(path: &str, data: &[u8], compression: Compression) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    match compression {
+        Compression::None => {
+            let mut file = file;
+            file.write_all(data)?;
+        }
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(file, 0)?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        Compression::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(file, bzip2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        Compression::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(file, 6);
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+    }
+    Ok(())
+}
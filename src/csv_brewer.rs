@@ -0,0 +1,554 @@
+// Standard Library
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+// External Library
+use chrono_tz::Tz;
+use dbp_schema::dbp_schema::RealWorldDataStructureInfo;
+
+use crate::brewing_demand::BrewingArgument;
+use crate::compression;
+use crate::filter_expr::{self, Datum, Filter, Record, Schema};
+use crate::text_encoding;
+use crate::time_parser;
+
+/// Prefix of the `csv_*` arguments; any of them selects this brewer.
+pub const ARG_PREFIX: &str = "csv_";
+const ARG_COLUMNS: &str = "csv_columns";
+const ARG_RENAME: &str = "csv_rename";
+const ARG_CAST: &str = "csv_cast";
+const ARG_DELIMITER: &str = "csv_delimiter";
+const ARG_OUTPUT_DELIMITER: &str = "csv_output_delimiter";
+const ARG_HEADER: &str = "csv_header";
+const ARG_OUTPUT_HEADER: &str = "csv_output_header";
+const ARG_ON_CAST_ERROR: &str = "csv_on_cast_error";
+
+/// Input file extensions this brewer reads.
+pub const EXTENSIONS: &[&str] = &[".csv", ".tsv", ".txt"];
+
+const TYPE_PREFIXES: [&str; 4] = ["xsd:", "http://www.w3.org/2001/XMLSchema#", "schema:", "https://schema.org/"];
+
+/// What a column is cast to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellType {
+    String,
+    Integer,
+    Decimal,
+    Boolean,
+    DateTime,
+    Date,
+}
+
+impl CellType {
+    /// Reads `dbp:itemType` (`xsd:integer`, `schema:Number`, ...) or a
+    /// `csv_cast` type name.
+    pub fn parse(item_type: &str) -> Option<Self> {
+        let name = TYPE_PREFIXES
+            .iter()
+            .find_map(|prefix| item_type.strip_prefix(prefix))
+            .unwrap_or(item_type);
+        match name.to_ascii_lowercase().as_str() {
+            "string" | "text" | "normalizedstring" | "token" => Some(CellType::String),
+            "integer" | "int" | "long" | "short" | "nonnegativeinteger" | "positiveinteger" => Some(CellType::Integer),
+            "decimal" | "double" | "float" | "number" => Some(CellType::Decimal),
+            "boolean" => Some(CellType::Boolean),
+            "datetime" => Some(CellType::DateTime),
+            "date" => Some(CellType::Date),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            CellType::String => "string",
+            CellType::Integer => "integer",
+            CellType::Decimal => "decimal",
+            CellType::Boolean => "boolean",
+            CellType::DateTime => "dateTime",
+            CellType::Date => "date",
+        }
+    }
+
+    /// The canonical text of a cell of this type. Empty cells stay empty.
+    fn cast(&self, cell: &str, tz: Tz) -> Result<String, String> {
+        let trimmed = cell.trim();
+        if trimmed.is_empty() || *self == CellType::String {
+            return Ok(cell.to_string());
+        }
+        let invalid = || format!("cannot cast {:?} to {}", cell, self.as_str());
+        match self {
+            CellType::String => Ok(cell.to_string()),
+            CellType::Integer => trimmed.parse::<i64>().map(|n| n.to_string()).map_err(|_| invalid()),
+            CellType::Decimal if is_decimal(trimmed) => Ok(trimmed.to_string()),
+            CellType::Decimal => Err(invalid()),
+            CellType::Boolean => match trimmed.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "y" | "t" => Ok("true".to_string()),
+                "false" | "0" | "no" | "n" | "f" => Ok("false".to_string()),
+                _ => Err(invalid()),
+            },
+            CellType::DateTime => time_parser::parse_time(&serde_json::Value::from(trimmed), tz)
+                .map(|dt| dt.to_rfc3339())
+                .map_err(|_| invalid()),
+            CellType::Date => time_parser::parse_time(&serde_json::Value::from(trimmed), tz)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Whether `text` is a finite decimal such as `-1.50` or `6.02e23`, kept as
+/// written so that no digits are lost.
+fn is_decimal(text: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let mantissa = mantissa.strip_prefix(['+', '-']).unwrap_or(mantissa);
+    let mantissa_ok = match mantissa.split_once('.') {
+        Some((int, frac)) => (int.is_empty() || digits(int)) && (frac.is_empty() || digits(frac)) && mantissa.len() > 1,
+        None => digits(mantissa),
+    };
+    mantissa_ok && exponent.is_none_or(|e| digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
+}
+
+/// A column described by a structure item, or found in the header.
+#[derive(Clone, Debug)]
+struct ColumnSpec {
+    name: String,
+    /// Header name, or 1-based column number.
+    structure_path: String,
+    cell_type: CellType,
+}
+
+/// How one CSV/TSV input is read, projected and written.
+#[derive(Clone, Debug)]
+pub struct CsvConfig {
+    specs: Vec<ColumnSpec>,
+    /// Output columns by name, in order; all described columns when empty.
+    columns: Vec<String>,
+    rename: HashMap<String, String>,
+    casts: HashMap<String, CellType>,
+    delimiter: u8,
+    output_delimiter: u8,
+    header: bool,
+    output_header: bool,
+    /// Write an empty cell instead of failing when a cast fails.
+    empty_on_cast_error: bool,
+    /// Rows to keep, from the `filter` argument.
+    filter: Option<Filter>,
+    /// Project, cast and rename the columns; otherwise rows are kept as read.
+    project: bool,
+    /// Zone of date-time cells without an offset.
+    time_zone: Tz,
+}
+
+/// Whether any `csv_*` argument is given.
+pub fn is_requested(arguments: &[BrewingArgument]) -> bool {
+    arguments.iter().any(|arg| arg.key.starts_with(ARG_PREFIX))
+}
+
+pub fn list(value: &str) -> Vec<String> {
+    value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+/// `a:b,c:d` as pairs.
+fn pairs(key: &str, value: &str) -> Result<Vec<(String, String)>, String> {
+    list(value)
+        .into_iter()
+        .map(|pair| match pair.split_once(':') {
+            Some((a, b)) => Ok((a.trim().to_string(), b.trim().to_string())),
+            None => Err(format!("{} entry {:?} is not name:value", key, pair)),
+        })
+        .collect()
+}
+
+fn delimiter(key: &str, value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        "comma" => Ok(b','),
+        "semicolon" => Ok(b';'),
+        "pipe" => Ok(b'|'),
+        "space" => Ok(b' '),
+        v if v.len() == 1 && v.is_ascii() => Ok(v.as_bytes()[0]),
+        v => Err(format!("{} {:?} is not a single ASCII character", key, v)),
+    }
+}
+
+pub fn boolean(key: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        v => Err(format!("{} {:?} is not true or false", key, v)),
+    }
+}
+
+impl CsvConfig {
+    /// The delimiter defaults to a tab for `text/tab-separated-values` or a
+    /// `.tsv` pattern and to a comma otherwise.
+    pub fn new(
+        arguments: &[BrewingArgument],
+        structure: Option<&RealWorldDataStructureInfo>,
+        encoding_format: Option<&str>,
+        pattern: &str,
+        time_zone: Tz,
+    ) -> Result<Self, String> {
+        let tsv = encoding_format.is_some_and(|f| text_encoding::media_type(f).eq_ignore_ascii_case("text/tab-separated-values"))
+            || compression::strip_extension(pattern).ends_with(".tsv");
+        let mut config = CsvConfig {
+            specs: Vec::new(),
+            columns: Vec::new(),
+            rename: HashMap::new(),
+            casts: HashMap::new(),
+            delimiter: if tsv { b'\t' } else { b',' },
+            output_delimiter: b',',
+            header: true,
+            output_header: true,
+            empty_on_cast_error: false,
+            filter: None,
+            project: true,
+            time_zone,
+        };
+        let mut output_delimiter = None;
+        for (i, item) in structure.map(|s| s.structure_items.as_slice()).unwrap_or_default().iter().enumerate() {
+            let structure_path = item.structure_path.clone().unwrap_or_else(|| (i + 1).to_string());
+            let cell_type = match item.item_type.as_deref() {
+                None => CellType::String,
+                Some(item_type) => CellType::parse(item_type).unwrap_or_else(|| {
+                    warn!("Unknown dbp:itemType {}; reading column {} as a string", item_type, structure_path);
+                    CellType::String
+                }),
+            };
+            config.specs.push(ColumnSpec {
+                name: item.name.clone().unwrap_or_else(|| structure_path.clone()),
+                structure_path,
+                cell_type,
+            });
+        }
+        for arg in arguments {
+            let (key, value) = (arg.key.as_str(), arg.value.as_str());
+            match key {
+                ARG_COLUMNS => config.columns = list(value),
+                ARG_RENAME => config.rename = pairs(key, value)?.into_iter().collect(),
+                ARG_CAST => {
+                    for (column, type_name) in pairs(key, value)? {
+                        let cell_type = CellType::parse(&type_name)
+                            .ok_or_else(|| format!("{} type {:?} of {} is unknown", key, type_name, column))?;
+                        config.casts.insert(column, cell_type);
+                    }
+                }
+                ARG_DELIMITER => config.delimiter = delimiter(key, value)?,
+                ARG_OUTPUT_DELIMITER => output_delimiter = Some(delimiter(key, value)?),
+                ARG_HEADER => config.header = boolean(key, value)?,
+                ARG_OUTPUT_HEADER => config.output_header = boolean(key, value)?,
+                ARG_ON_CAST_ERROR => {
+                    config.empty_on_cast_error = match value {
+                        "fail" => false,
+                        "empty" => true,
+                        v => return Err(format!("{} {:?} is not fail or empty", key, v)),
+                    }
+                }
+                key if key.starts_with(ARG_PREFIX) => return Err(format!("unknown brewing argument {}", key)),
+                _ => {}
+            }
+        }
+        // Unless given, outputs keep the input's delimiter.
+        config.output_delimiter = output_delimiter.unwrap_or(config.delimiter);
+        config.filter = filter_expr::from_arguments(arguments, &Schema::from_structure(structure, time_zone))?;
+        Ok(config)
+    }
+
+    /// Only filters rows, writing the kept ones as they were read.
+    pub fn for_filter(
+        arguments: &[BrewingArgument],
+        structure: Option<&RealWorldDataStructureInfo>,
+        encoding_format: Option<&str>,
+        pattern: &str,
+        time_zone: Tz,
+    ) -> Result<Self, String> {
+        let config = CsvConfig { project: false, ..Self::new(arguments, structure, encoding_format, pattern, time_zone)? };
+        if config.filter.is_none() {
+            return Err(format!("no {} argument", filter_expr::ARG_FILTER));
+        }
+        Ok(config)
+    }
+
+    /// Every column the output or the filter may refer to, given the header
+    /// (or, without one, the number of fields): described ones first, then
+    /// the remaining header columns as strings.
+    fn known_columns(&self, header: &[String], width: usize) -> Vec<(String, Option<usize>, CellType)> {
+        let mut known: Vec<(String, Option<usize>, CellType)> = self
+            .specs
+            .iter()
+            .map(|spec| (spec.name.clone(), column_index(&spec.structure_path, header, width), spec.cell_type))
+            .collect();
+        let names: Vec<String> = if header.is_empty() {
+            (1..=width).map(|n| n.to_string()).collect()
+        } else {
+            header.iter().map(|h| h.trim().to_string()).collect()
+        };
+        for (i, name) in names.into_iter().enumerate() {
+            if !known.iter().any(|(known_name, index, _)| *known_name == name || *index == Some(i)) {
+                known.push((name, Some(i), CellType::String));
+            }
+        }
+        known
+    }
+
+    /// Locates the output columns among the known ones: (input index,
+    /// output name, type) for each.
+    fn layout(&self, known: &[(String, Option<usize>, CellType)]) -> Result<Vec<(usize, String, CellType)>, String> {
+        let selected: Vec<String> = if !self.columns.is_empty() {
+            self.columns.clone()
+        } else if !self.specs.is_empty() {
+            self.specs.iter().map(|spec| spec.name.clone()).collect()
+        } else {
+            known.iter().map(|(name, _, _)| name.clone()).collect()
+        };
+        selected
+            .into_iter()
+            .map(|name| {
+                let (_, index, cell_type) = known
+                    .iter()
+                    .find(|(known_name, _, _)| *known_name == name)
+                    .ok_or_else(|| format!("column {} is neither a structure item nor in the header", name))?;
+                let index = index.ok_or_else(|| format!("column {} is not in the file", name))?;
+                let cell_type = self.casts.get(&name).copied().unwrap_or(*cell_type);
+                let output_name = self.rename.get(&name).cloned().unwrap_or(name);
+                Ok((index, output_name, cell_type))
+            })
+            .collect()
+    }
+}
+
+/// The index of a column given by header name or 1-based column number.
+fn column_index(structure_path: &str, header: &[String], width: usize) -> Option<usize> {
+    if let Some(i) = header.iter().position(|h| h.trim() == structure_path) {
+        return Some(i);
+    }
+    match structure_path.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= width => Some(n - 1),
+        _ => None,
+    }
+}
+
+/// A row as seen by the filter, with columns by name.
+struct CsvRecord<'a> {
+    record: &'a csv::StringRecord,
+    columns: &'a HashMap<String, usize>,
+}
+
+impl Record for CsvRecord<'_> {
+    fn field(&self, name: &str) -> Datum<'_> {
+        self.columns.get(name).and_then(|&i| self.record.get(i)).map_or(Datum::Missing, Datum::Text)
+    }
+}
+
+/// Filters the rows of one CSV/TSV input, then projects, reorders, casts
+/// and renames their columns.
+/// The output header is written only when `write_header` is set, so that
+/// several inputs can be joined under one header.
+pub fn brew(file: &mut dyn Read, config: &CsvConfig, write_header: bool) -> Result<String, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(config.delimiter)
+        .has_headers(config.header)
+        .flexible(true)
+        .from_reader(file);
+    let header: Vec<String> = if config.header {
+        reader.headers()?.iter().map(String::from).collect()
+    } else {
+        Vec::new()
+    };
+    let mut records = reader.records().peekable();
+    let width = match records.peek() {
+        Some(Ok(record)) => record.len(),
+        _ => header.len(),
+    };
+    let known = config.known_columns(&header, width);
+    let columns: HashMap<String, usize> = known.iter().filter_map(|(name, i, _)| Some((name.clone(), (*i)?))).collect();
+    let layout = if config.project { config.layout(&known)? } else { Vec::new() };
+
+    // Filtered rows are written as read, so ragged input stays ragged.
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(config.output_delimiter)
+        .flexible(true)
+        .from_writer(Vec::new());
+    if write_header && config.output_header {
+        if config.project {
+            writer.write_record(layout.iter().map(|(_, name, _)| name))?;
+        } else if config.header {
+            writer.write_record(&header)?;
+        }
+    }
+    for (row, record) in records.enumerate() {
+        let record = record?;
+        if let Some(filter) = &config.filter {
+            let kept = filter
+                .matches(&CsvRecord { record: &record, columns: &columns })
+                .map_err(|e| format!("row {}: {}", row + 1, e))?;
+            if !kept {
+                continue;
+            }
+        }
+        if !config.project {
+            writer.write_record(&record)?;
+            continue;
+        }
+        let mut cells = Vec::with_capacity(layout.len());
+        for (index, name, cell_type) in &layout {
+            let cell = record.get(*index).unwrap_or_default();
+            match cell_type.cast(cell, config.time_zone) {
+                Ok(cell) => cells.push(cell),
+                Err(_) if config.empty_on_cast_error => cells.push(String::new()),
+                // Rows are numbered from 1, after the header.
+                Err(e) => return Err(format!("row {}, column {}: {}", row + 1, name, e).into()),
+            }
+        }
+        writer.write_record(&cells)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbp_schema::dbp_schema::RealWorldDataStructureItem;
+
+    fn arguments(pairs: &[(&str, &str)]) -> Vec<BrewingArgument> {
+        pairs.iter().map(|(key, value)| BrewingArgument { key: key.to_string(), value: value.to_string() }).collect()
+    }
+
+    fn structure(items: &[(&str, &str, &str)]) -> RealWorldDataStructureInfo {
+        RealWorldDataStructureInfo {
+            structure_items: items
+                .iter()
+                .map(|(name, structure_path, item_type)| RealWorldDataStructureItem {
+                    name: Some(name.to_string()),
+                    structure_path: Some(structure_path.to_string()),
+                    item_type: Some(item_type.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn run(input: &str, arguments: &[(&str, &str)], structure: Option<&RealWorldDataStructureInfo>, pattern: &str) -> Result<String, String> {
+        let config = CsvConfig::new(&self::arguments(arguments), structure, None, pattern, Tz::Asia__Tokyo)?;
+        brew(&mut input.as_bytes(), &config, true).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn casts_cells_to_their_canonical_text() {
+        let cases = [
+            (CellType::Integer, " 42 ", Some("42")),
+            (CellType::Integer, "+7", Some("7")),
+            (CellType::Integer, "4.2", None),
+            (CellType::Decimal, "-1.50", Some("-1.50")),
+            (CellType::Decimal, "0.1000000000000000000001", Some("0.1000000000000000000001")),
+            (CellType::Decimal, "6.02E23", Some("6.02E23")),
+            (CellType::Decimal, ".5", Some(".5")),
+            (CellType::Decimal, "5.", Some("5.")),
+            (CellType::Decimal, "1e-3", Some("1e-3")),
+            (CellType::Decimal, "NaN", None),
+            (CellType::Decimal, "inf", None),
+            (CellType::Decimal, "-infinity", None),
+            (CellType::Decimal, ".", None),
+            (CellType::Decimal, "1e", None),
+            (CellType::Decimal, "1.2.3", None),
+            (CellType::Decimal, "0x10", None),
+            (CellType::Boolean, "Yes", Some("true")),
+            (CellType::Boolean, "0", Some("false")),
+            (CellType::Boolean, "maybe", None),
+            (CellType::DateTime, "2024-01-02 03:04:05", Some("2024-01-02T03:04:05+09:00")),
+            (CellType::Date, "2024-01-02T23:00:00+09:00", Some("2024-01-02")),
+            (CellType::Date, "yesterday", None),
+            (CellType::String, " kept as is ", Some(" kept as is ")),
+            (CellType::Integer, "  ", Some("  ")),
+        ];
+        for (cell_type, cell, expected) in cases {
+            assert_eq!(cell_type.cast(cell, Tz::Asia__Tokyo).ok().as_deref(), expected, "{:?} {:?}", cell_type, cell);
+        }
+    }
+
+    #[test]
+    fn reads_item_types_with_any_prefix() {
+        let cases = [
+            ("xsd:integer", Some(CellType::Integer)),
+            ("http://www.w3.org/2001/XMLSchema#dateTime", Some(CellType::DateTime)),
+            ("schema:Number", Some(CellType::Decimal)),
+            ("https://schema.org/Text", Some(CellType::String)),
+            ("Boolean", Some(CellType::Boolean)),
+            ("xsd:duration", None),
+        ];
+        for (item_type, expected) in cases {
+            assert_eq!(CellType::parse(item_type), expected, "{}", item_type);
+        }
+    }
+
+    #[test]
+    fn picks_delimiters_from_arguments_format_and_pattern() {
+        let input = "a\tb\n1\t2\n";
+        let cases = [
+            (vec![], "data.tsv", "a\tb\n1\t2\n"),
+            (vec![("csv_output_delimiter", "comma")], "data.tsv.gz", "a,b\n1,2\n"),
+            (vec![("csv_delimiter", "tab"), ("csv_output_delimiter", ";")], "data.txt", "a;b\n1;2\n"),
+            (vec![("csv_columns", "b")], "data.tsv", "b\n2\n"),
+        ];
+        for (arguments, pattern, expected) in cases {
+            assert_eq!(run(input, &arguments, None, pattern).unwrap(), expected, "{:?} {}", arguments, pattern);
+        }
+        let config = CsvConfig::new(&[], None, Some("text/tab-separated-values; charset=utf-8"), "data.txt", Tz::UTC).unwrap();
+        assert_eq!(config.delimiter, b'\t');
+        for value in ["", "::", "é"] {
+            assert!(run(input, &[("csv_delimiter", value)], None, "data.csv").is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn projects_renames_and_casts_by_header_or_position() {
+        let structure = structure(&[("id", "1", "xsd:integer"), ("temp", "temperature", "xsd:decimal"), ("at", "time", "xsd:dateTime")]);
+        let input = "id,temperature,time,note\n007,21.50,2024-01-02 03:00:00,x\n";
+        let cases = [
+            (vec![], "id,temp,at\n7,21.50,2024-01-02T03:00:00+09:00\n"),
+            (vec![("csv_rename", "temp:celsius")], "id,celsius,at\n7,21.50,2024-01-02T03:00:00+09:00\n"),
+            (vec![("csv_columns", "note,id"), ("csv_cast", "id:string")], "note,id\nx,007\n"),
+            (vec![("csv_output_header", "false"), ("csv_columns", "temp")], "21.50\n"),
+        ];
+        for (arguments, expected) in cases {
+            assert_eq!(run(input, &arguments, Some(&structure), "data.csv").unwrap(), expected, "{:?}", arguments);
+        }
+        assert!(run(input, &[("csv_columns", "missing")], Some(&structure), "data.csv").is_err());
+    }
+
+    #[test]
+    fn reads_files_without_a_header_by_position() {
+        let structure = structure(&[("b", "2", "xsd:integer"), ("a", "1", "xsd:string")]);
+        let input = "x,1\ny,2\n";
+        assert_eq!(run(input, &[("csv_header", "false")], Some(&structure), "data.csv").unwrap(), "b,a\n1,x\n2,y\n");
+        assert_eq!(run(input, &[("csv_header", "false")], None, "data.csv").unwrap(), "1,2\nx,1\ny,2\n");
+        let config = CsvConfig::new(&arguments(&[("csv_header", "no")]), None, None, "data.csv", Tz::UTC).unwrap();
+        assert_eq!(brew(&mut input.as_bytes(), &config, false).unwrap(), "x,1\ny,2\n");
+    }
+
+    #[test]
+    fn pads_short_rows_and_reports_bad_cells_by_row() {
+        let structure = structure(&[("a", "a", "xsd:string"), ("n", "n", "xsd:integer")]);
+        let input = "a,n\nx\ny,2,extra\nz,oops\n";
+        assert_eq!(
+            run(input, &[("csv_on_cast_error", "empty")], Some(&structure), "data.csv").unwrap(),
+            "a,n\nx,\ny,2\nz,\n"
+        );
+        let error = run(input, &[], Some(&structure), "data.csv").unwrap_err();
+        assert!(error.contains("row 3, column n"), "{}", error);
+    }
+
+    #[test]
+    fn filters_keep_ragged_rows_as_read() {
+        let input = "a,n\nx\ny,2,extra\nz,3\n";
+        let config = CsvConfig::for_filter(&arguments(&[("filter", "a != 'z'")]), None, None, "data.csv", Tz::UTC).unwrap();
+        assert_eq!(brew(&mut input.as_bytes(), &config, true).unwrap(), "a,n\nx\ny,2,extra\n");
+        assert!(CsvConfig::for_filter(&[], None, None, "data.csv", Tz::UTC).is_err());
+    }
+}
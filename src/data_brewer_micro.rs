@@ -1,13 +1,14 @@
-use std::io::{BufReader, Read};
-use std::fs::File;
-use std::error::Error;
-use serde_json::{Map, Value};
-
-pub fn data_brewer_sample(file: &File, arg: Map<String, Value>) -> Result<String, Box<dyn Error>> {
-    // Applies data brewing logic using 'arg'.
-    // This is a sample and doesn't actually use 'arg'.
-    let mut reader = BufReader::new(file);
-    let mut file_content = String::new();
-    reader.read_to_string(&mut file_content)?;
-    Ok(file_content)
+use std::io::{BufReader, Read};
+use std::error::Error;
+use serde_json::{Map, Value};
+
+pub const EXTENSIONS: &[&str] = &[".extention"];
+
+pub fn data_brewer_sample(file: &mut dyn Read, arg: Map<String, Value>) -> Result<String, Box<dyn Error>> {
+    // Applies data brewing logic using 'arg'.
+    // This is a sample and doesn't actually use 'arg'.
+    let mut reader = BufReader::new(file);
+    let mut file_content = String::new();
+    reader.read_to_string(&mut file_content)?;
+    Ok(file_content)
 }
\ No newline at end of file
@@ -0,0 +1,988 @@
+// Standard Library
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// External Library
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use dbp_schema::dbp_schema::RealWorldDataStructureInfo;
+use serde_json::Value;
+use unicode_width::UnicodeWidthChar;
+
+use crate::brewing_demand::BrewingArgument;
+use crate::csv_brewer::CellType;
+use crate::time_parser;
+
+/// Brewing argument holding a filter expression such as
+/// `temperature > 30 && station == "A01"`.
+pub const ARG_FILTER: &str = "filter";
+
+/// Byte range of a token in the expression.
+type Span = (usize, usize);
+
+/// Type of a field, a value or a sub-expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldType {
+    /// Not described; typed by its values when the expression is evaluated
+    Any,
+    Null,
+    Boolean,
+    Number,
+    String,
+    DateTime,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Any => "any",
+            FieldType::Null => "null",
+            FieldType::Boolean => "boolean",
+            FieldType::Number => "number",
+            FieldType::String => "string",
+            FieldType::DateTime => "dateTime",
+        }
+    }
+}
+
+impl From<CellType> for FieldType {
+    fn from(cell_type: CellType) -> Self {
+        match cell_type {
+            CellType::String => FieldType::String,
+            CellType::Integer | CellType::Decimal => FieldType::Number,
+            CellType::Boolean => FieldType::Boolean,
+            CellType::DateTime | CellType::Date => FieldType::DateTime,
+        }
+    }
+}
+
+/// Field types an expression is checked against. With structure items only
+/// the described fields may be used; without them any field is accepted
+/// and typed by its values.
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    fields: HashMap<String, FieldType>,
+    names: Vec<String>,
+    /// Zone of date-time literals and fields without an offset.
+    time_zone: Tz,
+}
+
+impl Schema {
+    /// Fields are named by `schema:name`, or else `dbp:structurePath`, and
+    /// typed by `dbp:itemType`; items without a known type are `any`.
+    pub fn from_structure(structure: Option<&RealWorldDataStructureInfo>, time_zone: Tz) -> Self {
+        let mut schema = Schema { time_zone, ..Default::default() };
+        for (i, item) in structure.map(|s| s.structure_items.as_slice()).unwrap_or_default().iter().enumerate() {
+            let name = item
+                .name
+                .clone()
+                .or_else(|| item.structure_path.clone())
+                .unwrap_or_else(|| (i + 1).to_string());
+            let field_type = item
+                .item_type
+                .as_deref()
+                .and_then(CellType::parse)
+                .map_or(FieldType::Any, FieldType::from);
+            if schema.fields.insert(name.clone(), field_type).is_none() {
+                schema.names.push(name);
+            }
+        }
+        schema
+    }
+
+    fn field(&self, name: &str) -> Option<FieldType> {
+        if self.names.is_empty() {
+            return Some(FieldType::Any);
+        }
+        self.fields.get(name).copied()
+    }
+}
+
+/// A field value as stored in a record.
+pub enum Datum<'a> {
+    Missing,
+    /// A CSV cell; empty cells are null.
+    Text(&'a str),
+    Json(&'a Value),
+}
+
+/// Access to the fields of one record by name.
+pub trait Record {
+    fn field(&self, name: &str) -> Datum<'_>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    fn is_equality(&self) -> bool {
+        matches!(self, CompareOp::Eq | CompareOp::Ne)
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Field(String),
+    String(String),
+    Number(f64),
+    True,
+    False,
+    Null,
+    And,
+    Or,
+    Not,
+    Compare(CompareOp),
+    LParen,
+    RParen,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Field(name) => format!("field {}", name),
+            Token::String(text) => format!("string {:?}", text),
+            Token::Number(n) => format!("number {}", n),
+            Token::True => "true".to_string(),
+            Token::False => "false".to_string(),
+            Token::Null => "null".to_string(),
+            Token::And => "&&".to_string(),
+            Token::Or => "||".to_string(),
+            Token::Not => "!".to_string(),
+            Token::Compare(op) => op.as_str().to_string(),
+            Token::LParen => "(".to_string(),
+            Token::RParen => ")".to_string(),
+            Token::End => "end of the expression".to_string(),
+        }
+    }
+}
+
+const SYMBOLS: [(&str, Token); 11] = [
+    ("&&", Token::And),
+    ("||", Token::Or),
+    ("==", Token::Compare(CompareOp::Eq)),
+    ("!=", Token::Compare(CompareOp::Ne)),
+    ("<=", Token::Compare(CompareOp::Le)),
+    (">=", Token::Compare(CompareOp::Ge)),
+    ("<", Token::Compare(CompareOp::Lt)),
+    (">", Token::Compare(CompareOp::Gt)),
+    ("!", Token::Not),
+    ("(", Token::LParen),
+    (")", Token::RParen),
+];
+
+/// Formats an error pointing at a token of the expression:
+///
+/// ```text
+/// column 1: unknown field temprature
+///   temprature > 30
+///   ^^^^^^^^^^
+/// ```
+fn error_at(source: &str, span: Span, message: &str) -> String {
+    let column = source[..span.0].chars().count();
+    let line: String = source.chars().map(|c| if c.is_whitespace() { ' ' } else { c }).collect();
+    format!(
+        "column {}: {}\n  {}\n  {}{}",
+        column + 1,
+        message,
+        line,
+        " ".repeat(display_width(&source[..span.0])),
+        "^".repeat(display_width(&source[span.0..span.1]).max(1))
+    )
+}
+
+/// Terminal columns taken by text as `error_at` prints it, so that carets
+/// line up under wide characters such as kanji.
+fn display_width(text: &str) -> usize {
+    text.chars().map(|c| if c.is_whitespace() { 1 } else { c.width().unwrap_or(0) }).sum()
+}
+
+fn is_field_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_field_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+fn lex(source: &str) -> Result<Vec<(Token, Span)>, String> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = source[pos..].chars().next() {
+        let rest = &source[pos..];
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+        if let Some((symbol, token)) = SYMBOLS.iter().find(|(symbol, _)| rest.starts_with(symbol)) {
+            tokens.push((token.clone(), (pos, pos + symbol.len())));
+            pos += symbol.len();
+            continue;
+        }
+        let start = pos;
+        let token = match c {
+            '=' => return Err(error_at(source, (pos, pos + 1), "use == to compare")),
+            '&' => return Err(error_at(source, (pos, pos + 1), "use && to join conditions")),
+            '|' => return Err(error_at(source, (pos, pos + 1), "use || to join conditions")),
+            '"' | '\'' => {
+                let mut text = String::new();
+                let mut chars = rest.char_indices().skip(1);
+                loop {
+                    match chars.next() {
+                        None => return Err(error_at(source, (start, source.len()), "unterminated string")),
+                        Some((i, q)) if q == c => {
+                            pos += i + 1;
+                            break;
+                        }
+                        Some((i, '\\')) => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, e)) if e == '\\' || e == '"' || e == '\'' => text.push(e),
+                            _ => {
+                                return Err(error_at(source, (start + i, start + i + 2), "unknown escape sequence"));
+                            }
+                        },
+                        Some((_, ch)) => text.push(ch),
+                    }
+                }
+                Token::String(text)
+            }
+            '`' => match rest[1..].find('`') {
+                Some(end) => {
+                    pos += end + 2;
+                    Token::Field(rest[1..end + 1].to_string())
+                }
+                None => return Err(error_at(source, (start, source.len()), "unterminated `field name`")),
+            },
+            c if c.is_ascii_digit() || ((c == '-' || c == '.') && rest[1..].starts_with(|d: char| d.is_ascii_digit())) => {
+                let mut len = c.len_utf8();
+                let mut previous = c;
+                for d in rest[len..].chars() {
+                    let exponent_sign = (d == '-' || d == '+') && (previous == 'e' || previous == 'E');
+                    if !(d.is_ascii_digit() || d == '.' || d == 'e' || d == 'E' || exponent_sign) {
+                        break;
+                    }
+                    len += 1;
+                    previous = d;
+                }
+                pos += len;
+                let text = &rest[..len];
+                Token::Number(
+                    text.parse()
+                        .map_err(|_| error_at(source, (start, pos), &format!("invalid number {}", text)))?,
+                )
+            }
+            c if is_field_start(c) => {
+                let len = rest.find(|ch: char| !is_field_char(ch)).unwrap_or(rest.len());
+                pos += len;
+                match &rest[..len] {
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    "null" => Token::Null,
+                    name => Token::Field(name.to_string()),
+                }
+            }
+            c => {
+                return Err(error_at(source, (pos, pos + c.len_utf8()), &format!("unexpected character {:?}", c)));
+            }
+        };
+        tokens.push((token, (start, pos)));
+    }
+    tokens.push((Token::End, (source.len(), source.len())));
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    /// A string compared with a `dateTime` field, parsed when checked.
+    DateTime(DateTime<FixedOffset>),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Literal, Span),
+    /// A field with the type it is read as, set when checked.
+    Field(String, FieldType, Span),
+    Not(Box<Expr>, Span),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Span, Box<Expr>),
+}
+
+impl Expr {
+    fn span(&self) -> Span {
+        match self {
+            Expr::Literal(_, span) | Expr::Field(_, _, span) => *span,
+            Expr::Not(inner, span) => (span.0, inner.span().1),
+            Expr::And(left, right) | Expr::Or(left, right) | Expr::Compare(left, _, _, right) => {
+                (left.span().0, right.span().1)
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn next(&mut self) -> (Token, Span) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while *self.peek() == Token::Or {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while *self.peek() == Token::And {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if *self.peek() == Token::Not {
+            let (_, span) = self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?), span));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.primary()?;
+        let Token::Compare(op) = *self.peek() else {
+            return Ok(left);
+        };
+        let (_, op_span) = self.next();
+        let right = self.primary()?;
+        if let (Token::Compare(_), span) = &self.tokens[self.pos] {
+            return Err(error_at(self.source, *span, "comparisons cannot be chained; join them with &&"));
+        }
+        Ok(Expr::Compare(Box::new(left), op, op_span, Box::new(right)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let (token, span) = self.next();
+        let literal = match token {
+            Token::Field(name) => return Ok(Expr::Field(name, FieldType::Any, span)),
+            Token::LParen => {
+                let inner = self.or()?;
+                let (token, close) = self.next();
+                if token != Token::RParen {
+                    return Err(error_at(self.source, close, &format!("expected ) but found {}", token.describe())));
+                }
+                return Ok(inner);
+            }
+            Token::String(text) => Literal::String(text),
+            Token::Number(n) => Literal::Number(n),
+            Token::True => Literal::Boolean(true),
+            Token::False => Literal::Boolean(false),
+            Token::Null => Literal::Null,
+            token => {
+                return Err(error_at(
+                    self.source,
+                    span,
+                    &format!("expected a field or a value but found {}", token.describe()),
+                ));
+            }
+        };
+        Ok(Expr::Literal(literal, span))
+    }
+}
+
+/// A parsed and type-checked filter expression.
+///
+/// Conditions are comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) of fields
+/// and values (numbers, `"strings"`, `true`, `false`, `null`), joined with
+/// `&&`, `||` and `!` and grouped with parentheses. A missing field or an
+/// empty cell is `null`: it only equals `null` and is never ordered.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+    time_zone: Tz,
+}
+
+impl Filter {
+    pub fn new(source: &str, schema: &Schema) -> Result<Self, String> {
+        let mut parser = Parser { source, tokens: lex(source)?, pos: 0 };
+        let mut expr = parser.or()?;
+        let (token, span) = parser.next();
+        if token != Token::End {
+            return Err(error_at(source, span, &format!("unexpected {}", token.describe())));
+        }
+        let mut filter = Filter { source: source.to_string(), expr: expr.clone(), time_zone: schema.time_zone };
+        let result = filter.check(&mut expr, schema)?;
+        filter.condition_type(result, expr.span())?;
+        filter.expr = expr;
+        Ok(filter)
+    }
+
+    fn error(&self, span: Span, message: &str) -> String {
+        error_at(&self.source, span, message)
+    }
+
+    fn condition_type(&self, found: FieldType, span: Span) -> Result<(), String> {
+        match found {
+            FieldType::Boolean | FieldType::Any => Ok(()),
+            found => Err(self.error(span, &format!("expected a condition but found a {}", found.as_str()))),
+        }
+    }
+
+    /// Types every sub-expression, resolving fields against the schema.
+    fn check(&self, expr: &mut Expr, schema: &Schema) -> Result<FieldType, String> {
+        match expr {
+            Expr::Literal(literal, _) => Ok(match literal {
+                Literal::Null => FieldType::Null,
+                Literal::Boolean(_) => FieldType::Boolean,
+                Literal::Number(_) => FieldType::Number,
+                Literal::String(_) => FieldType::String,
+                Literal::DateTime(_) => FieldType::DateTime,
+            }),
+            Expr::Field(name, field_type, span) => {
+                *field_type = schema.field(name).ok_or_else(|| {
+                    self.error(
+                        *span,
+                        &format!("unknown field {}; the structure info describes {}", name, schema.names.join(", ")),
+                    )
+                })?;
+                Ok(*field_type)
+            }
+            Expr::Not(inner, _) => {
+                let found = self.check(inner, schema)?;
+                self.condition_type(found, inner.span())?;
+                Ok(FieldType::Boolean)
+            }
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                for side in [left, right] {
+                    let found = self.check(side, schema)?;
+                    self.condition_type(found, side.span())?;
+                }
+                Ok(FieldType::Boolean)
+            }
+            Expr::Compare(left, op, op_span, right) => {
+                let left_type = self.check(left, schema)?;
+                let right_type = self.check(right, schema)?;
+                let incompatible = || {
+                    self.error(
+                        *op_span,
+                        &format!("cannot compare a {} with a {}", left_type.as_str(), right_type.as_str()),
+                    )
+                };
+                match (left_type, right_type) {
+                    (FieldType::Null, _) | (_, FieldType::Null) if !op.is_equality() => {
+                        Err(self.error(*op_span, "null can only be compared with == or !="))
+                    }
+                    (FieldType::Null, _) | (_, FieldType::Null) | (FieldType::Any, _) | (_, FieldType::Any) => Ok(()),
+                    (FieldType::Boolean, FieldType::Boolean) if !op.is_equality() => {
+                        Err(self.error(*op_span, "booleans can only be compared with == or !="))
+                    }
+                    (a, b) if a == b => Ok(()),
+                    (FieldType::DateTime, FieldType::String) => self.time_literal(right).ok_or_else(incompatible)?,
+                    (FieldType::String, FieldType::DateTime) => self.time_literal(left).ok_or_else(incompatible)?,
+                    _ => Err(incompatible()),
+                }?;
+                Ok(FieldType::Boolean)
+            }
+        }
+    }
+
+    /// Reads a string literal compared with a `dateTime` field as a time;
+    /// `None` if the expression is not a string literal.
+    fn time_literal(&self, expr: &mut Expr) -> Option<Result<(), String>> {
+        let Expr::Literal(literal, span) = expr else {
+            return None;
+        };
+        let Literal::String(text) = literal else {
+            return None;
+        };
+        Some(match time_parser::parse_time(&Value::from(text.as_str()), self.time_zone) {
+            Ok(dt) => {
+                *literal = Literal::DateTime(dt);
+                Ok(())
+            }
+            Err(e) => Err(self.error(*span, &format!("{:?} is not a time: {}", text, e))),
+        })
+    }
+
+    /// Whether a record passes the filter.
+    pub fn matches(&self, record: &dyn Record) -> Result<bool, String> {
+        let value = self.eval(&self.expr, record)?;
+        self.truth(value, self.expr.span())
+    }
+
+    fn truth(&self, value: Scalar, span: Span) -> Result<bool, String> {
+        match value {
+            Scalar::Boolean(b) => Ok(b),
+            Scalar::Null => Ok(false),
+            Scalar::String(text) => parse_boolean(&text)
+                .ok_or_else(|| self.error(span, &format!("{:?} is not true or false", text))),
+            Scalar::Number(n) => Err(self.error(span, &format!("{} is not true or false", n))),
+            Scalar::DateTime(dt) => Err(self.error(span, &format!("{} is not true or false", dt.to_rfc3339()))),
+        }
+    }
+
+    fn eval<'a>(&self, expr: &'a Expr, record: &'a dyn Record) -> Result<Scalar<'a>, String> {
+        match expr {
+            Expr::Literal(literal, _) => Ok(match literal {
+                Literal::Null => Scalar::Null,
+                Literal::Boolean(b) => Scalar::Boolean(*b),
+                Literal::Number(n) => Scalar::Number(*n),
+                Literal::String(text) => Scalar::String(Cow::Borrowed(text)),
+                Literal::DateTime(dt) => Scalar::DateTime(*dt),
+            }),
+            Expr::Field(name, field_type, span) => {
+                read(record.field(name), *field_type, self.time_zone).map_err(|e| self.error(*span, &format!("field {} {}", name, e)))
+            }
+            Expr::Not(inner, _) => Ok(Scalar::Boolean(!self.truth(self.eval(inner, record)?, inner.span())?)),
+            Expr::And(left, right) => Ok(Scalar::Boolean(
+                self.truth(self.eval(left, record)?, left.span())?
+                    && self.truth(self.eval(right, record)?, right.span())?,
+            )),
+            Expr::Or(left, right) => Ok(Scalar::Boolean(
+                self.truth(self.eval(left, record)?, left.span())?
+                    || self.truth(self.eval(right, record)?, right.span())?,
+            )),
+            Expr::Compare(left, op, _, right) => {
+                let (left, right) = (self.eval(left, record)?, self.eval(right, record)?);
+                Ok(Scalar::Boolean(compare(&left, *op, &right, self.time_zone)))
+            }
+        }
+    }
+}
+
+/// The filter given in the brewing arguments, checked against the schema.
+pub fn from_arguments(arguments: &[BrewingArgument], schema: &Schema) -> Result<Option<Filter>, String> {
+    let mut filters = arguments.iter().filter(|arg| arg.key == ARG_FILTER);
+    let Some(arg) = filters.next() else {
+        return Ok(None);
+    };
+    if filters.next().is_some() {
+        return Err(format!("only one {} argument may be given; join conditions with &&", ARG_FILTER));
+    }
+    Filter::new(&arg.value, schema).map(Some)
+}
+
+/// Whether a `filter` argument is given.
+pub fn is_requested(arguments: &[BrewingArgument]) -> bool {
+    arguments.iter().any(|arg| arg.key == ARG_FILTER)
+}
+
+#[derive(Clone, Debug)]
+enum Scalar<'a> {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(Cow<'a, str>),
+    DateTime(DateTime<FixedOffset>),
+}
+
+fn parse_boolean(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "y" | "t" => Some(true),
+        "false" | "0" | "no" | "n" | "f" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_time(value: &Value, tz: Tz) -> Option<DateTime<FixedOffset>> {
+    time_parser::parse_time(value, tz).ok()
+}
+
+/// Reads a field as its declared type; `any` fields keep their own type.
+fn read(datum: Datum<'_>, field_type: FieldType, tz: Tz) -> Result<Scalar<'_>, String> {
+    let invalid = |shown: String| format!("{} is not a {}", shown, field_type.as_str());
+    match datum {
+        Datum::Missing => Ok(Scalar::Null),
+        Datum::Text(text) if text.trim().is_empty() => Ok(Scalar::Null),
+        Datum::Text(text) => match field_type {
+            FieldType::Any | FieldType::Null | FieldType::String => Ok(Scalar::String(Cow::Borrowed(text))),
+            FieldType::Number => text.trim().parse().map(Scalar::Number).map_err(|_| invalid(format!("{:?}", text))),
+            FieldType::Boolean => parse_boolean(text).map(Scalar::Boolean).ok_or_else(|| invalid(format!("{:?}", text))),
+            FieldType::DateTime => parse_time(&Value::from(text), tz)
+                .map(Scalar::DateTime)
+                .ok_or_else(|| invalid(format!("{:?}", text))),
+        },
+        Datum::Json(value) => match (value, field_type) {
+            (Value::Null, _) => Ok(Scalar::Null),
+            (Value::Array(_), _) => Err("is an array, not a value".to_string()),
+            (Value::Object(_), _) => Err("is an object, not a value".to_string()),
+            (Value::String(text), _) => read(Datum::Text(text), field_type, tz),
+            (Value::Number(n), FieldType::DateTime) => parse_time(value, tz).map(Scalar::DateTime).ok_or_else(|| invalid(n.to_string())),
+            (Value::Number(n), FieldType::Any | FieldType::Null | FieldType::Number) => {
+                n.as_f64().map(Scalar::Number).ok_or_else(|| invalid(n.to_string()))
+            }
+            (Value::Bool(b), FieldType::Any | FieldType::Null | FieldType::Boolean) => Ok(Scalar::Boolean(*b)),
+            (value, _) => Err(invalid(value.to_string())),
+        },
+    }
+}
+
+/// Orders two values of the same type. Text of an `any` field is read as
+/// the type of the value it is compared with; `None` if it cannot be.
+fn order(left: &Scalar, right: &Scalar, tz: Tz) -> Option<Ordering> {
+    match (left, right) {
+        (Scalar::Boolean(a), Scalar::Boolean(b)) => Some(a.cmp(b)),
+        (Scalar::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+        (Scalar::String(a), Scalar::String(b)) => Some(a.cmp(b)),
+        (Scalar::DateTime(a), Scalar::DateTime(b)) => Some(a.cmp(b)),
+        (Scalar::String(text), other) => order(&coerce(text, other, tz)?, other, tz),
+        (other, Scalar::String(text)) => order(other, &coerce(text, other, tz)?, tz),
+        _ => None,
+    }
+}
+
+fn coerce(text: &str, like: &Scalar, tz: Tz) -> Option<Scalar<'static>> {
+    match like {
+        Scalar::Boolean(_) => parse_boolean(text).map(Scalar::Boolean),
+        Scalar::Number(_) => text.trim().parse().ok().map(Scalar::Number),
+        Scalar::DateTime(_) => parse_time(&Value::from(text), tz).map(Scalar::DateTime),
+        _ => None,
+    }
+}
+
+fn compare(left: &Scalar, op: CompareOp, right: &Scalar, tz: Tz) -> bool {
+    match (left, right) {
+        (Scalar::Null, Scalar::Null) => op.holds(Ordering::Equal) && op.is_equality(),
+        (Scalar::Null, _) | (_, Scalar::Null) => op == CompareOp::Ne,
+        _ => match order(left, right, tz) {
+            Some(ordering) => op.holds(ordering),
+            // Values that cannot be compared are never equal or ordered.
+            None => op == CompareOp::Ne,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbp_schema::dbp_schema::RealWorldDataStructureItem;
+    use serde_json::json;
+
+    /// A CSV row: every field is text and empty cells are null.
+    struct Row(HashMap<&'static str, &'static str>);
+
+    impl Record for Row {
+        fn field(&self, name: &str) -> Datum<'_> {
+            self.0.get(name).map_or(Datum::Missing, |text| Datum::Text(text))
+        }
+    }
+
+    struct Object(Value);
+
+    impl Record for Object {
+        fn field(&self, name: &str) -> Datum<'_> {
+            self.0.get(name).map_or(Datum::Missing, Datum::Json)
+        }
+    }
+
+    fn typed_schema() -> Schema {
+        let items = [
+            ("temperature", "xsd:decimal"),
+            ("station", "xsd:string"),
+            ("observed", "xsd:dateTime"),
+            ("valid", "xsd:boolean"),
+            ("note", "xsd:string"),
+        ];
+        let structure = RealWorldDataStructureInfo {
+            structure_items: items
+                .iter()
+                .map(|(name, item_type)| RealWorldDataStructureItem {
+                    name: Some(name.to_string()),
+                    item_type: Some(item_type.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Schema::from_structure(Some(&structure), Tz::Asia__Tokyo)
+    }
+
+    #[test]
+    fn lexes_numbers_strings_and_fields() {
+        let cases = [
+            (
+                "1 -2 .5 1.5e3 2E-2",
+                vec![Token::Number(1.0), Token::Number(-2.0), Token::Number(0.5), Token::Number(1500.0), Token::Number(0.02)],
+            ),
+            (
+                r#""a\"b" 'c\'d' "\t\n\\""#,
+                vec![Token::String("a\"b".into()), Token::String("c'd".into()), Token::String("\t\n\\".into())],
+            ),
+            (
+                "`air temp` x.y _z 気温",
+                vec![Token::Field("air temp".into()), Token::Field("x.y".into()), Token::Field("_z".into()), Token::Field("気温".into())],
+            ),
+            ("true false null", vec![Token::True, Token::False, Token::Null]),
+            (
+                "a<=b>=c!=d==e<f>g",
+                vec![
+                    Token::Field("a".into()),
+                    Token::Compare(CompareOp::Le),
+                    Token::Field("b".into()),
+                    Token::Compare(CompareOp::Ge),
+                    Token::Field("c".into()),
+                    Token::Compare(CompareOp::Ne),
+                    Token::Field("d".into()),
+                    Token::Compare(CompareOp::Eq),
+                    Token::Field("e".into()),
+                    Token::Compare(CompareOp::Lt),
+                    Token::Field("f".into()),
+                    Token::Compare(CompareOp::Gt),
+                    Token::Field("g".into()),
+                ],
+            ),
+            ("!(a)&&b||c", vec![
+                Token::Not,
+                Token::LParen,
+                Token::Field("a".into()),
+                Token::RParen,
+                Token::And,
+                Token::Field("b".into()),
+                Token::Or,
+                Token::Field("c".into()),
+            ]),
+        ];
+        for (source, mut expected) in cases {
+            expected.push(Token::End);
+            let tokens: Vec<Token> = lex(source).unwrap().into_iter().map(|(token, _)| token).collect();
+            assert_eq!(tokens, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn parses_valid_expressions() {
+        let typed = [
+            "temperature > 30",
+            "temperature >= -1.5e2 && station == \"A01\"",
+            "!(valid == true) || station != null",
+            "observed >= \"2023-09-01T00:00:00Z\"",
+            "\"2023-09-01\" < observed",
+            "`temperature` < .5",
+            "station == 'it\\'s'",
+            "valid",
+            "note == null && !valid",
+        ];
+        for source in typed {
+            assert!(Filter::new(source, &typed_schema()).is_ok(), "{}", source);
+        }
+        let untyped = ["a.b == 1 || c", "x == \"\\t\\n\\\\\\\"\"", "unknown", "(a)", "気温 >= 30"];
+        for source in untyped {
+            assert!(Filter::new(source, &Schema::default()).is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        let cases = [
+            (
+                "temprature > 30",
+                "column 1: unknown field temprature; the structure info describes temperature, station, observed, valid, note",
+                "^^^^^^^^^^",
+            ),
+            ("temperature = 30", "column 13: use == to compare", "            ^"),
+            ("valid & valid", "column 7: use && to join conditions", "      ^"),
+            ("valid | valid", "column 7: use || to join conditions", "      ^"),
+            ("station == \"A01", "column 12: unterminated string", "           ^^^^"),
+            ("station == \"a\\qb\"", "column 14: unknown escape sequence", "             ^^"),
+            ("`temperature > 30", "column 1: unterminated `field name`", "^^^^^^^^^^^^^^^^^"),
+            ("temperature > 1e", "column 15: invalid number 1e", "              ^^"),
+            ("temperature > 30 # x", "column 18: unexpected character '#'", "                 ^"),
+            (
+                "temperature > 1 > 0",
+                "column 17: comparisons cannot be chained; join them with &&",
+                "                ^",
+            ),
+            (
+                "(temperature > 30",
+                "column 18: expected ) but found end of the expression",
+                "                 ^",
+            ),
+            (
+                "temperature > && valid",
+                "column 15: expected a field or a value but found &&",
+                "              ^^",
+            ),
+            ("temperature > 30)", "column 17: unexpected )", "                ^"),
+            ("station < 30", "column 9: cannot compare a string with a number", "        ^"),
+            ("temperature < null", "column 13: null can only be compared with == or !=", "            ^"),
+            ("valid < true", "column 7: booleans can only be compared with == or !=", "      ^"),
+            ("temperature", "column 1: expected a condition but found a number", "^^^^^^^^^^^"),
+            ("!station", "column 2: expected a condition but found a string", " ^^^^^^^"),
+        ];
+        for (source, message, carets) in cases {
+            let error = Filter::new(source, &typed_schema()).unwrap_err();
+            assert_eq!(error, format!("{}\n  {}\n  {}", message, source, carets), "{}", source);
+        }
+        let error = Filter::new("observed > \"yesterday\"", &typed_schema()).unwrap_err();
+        assert!(error.starts_with("column 12: \"yesterday\" is not a time"), "{}", error);
+        assert!(error.ends_with("\n  observed > \"yesterday\"\n             ^^^^^^^^^^^"), "{}", error);
+    }
+
+    #[test]
+    fn carets_line_up_under_wide_characters_and_tabs() {
+        let cases = [
+            ("気温 = 3", "column 4: use == to compare\n  気温 = 3\n       ^"),
+            ("a\t= 1", "column 3: use == to compare\n  a = 1\n    ^"),
+            ("\"温度\" & a", "column 6: use && to join conditions\n  \"温度\" & a\n         ^"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(Filter::new(source, &Schema::default()).unwrap_err(), expected, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn matches_csv_rows_by_declared_type() {
+        let row = Row(HashMap::from([
+            ("temperature", "31.5"),
+            ("station", "A01"),
+            ("observed", "2023-09-01T09:00:00"),
+            ("valid", "yes"),
+            ("note", ""),
+        ]));
+        let cases = [
+            ("temperature > 30", true),
+            ("temperature > 30 && station == \"A02\"", false),
+            ("temperature > 30 && station == 'A01'", true),
+            // Times without an offset are read in the schema's zone.
+            ("observed == \"2023-09-01T00:00:00Z\"", true),
+            ("observed < \"2023-09-01T09:00:00+09:00\"", false),
+            ("valid", true),
+            ("!valid", false),
+            ("valid == true", true),
+            // Empty cells are null: equal only to null and never ordered.
+            ("note == null", true),
+            ("note != null", false),
+            ("note == \"\"", false),
+            ("note != \"x\"", true),
+            ("station != null", true),
+        ];
+        for (source, expected) in cases {
+            let filter = Filter::new(source, &typed_schema()).unwrap();
+            assert_eq!(filter.matches(&row), Ok(expected), "{}", source);
+        }
+
+        let row = Row(HashMap::from([("temperature", "warm")]));
+        let error = Filter::new("temperature > 30", &typed_schema()).unwrap().matches(&row).unwrap_err();
+        assert_eq!(error, "column 1: field temperature \"warm\" is not a number\n  temperature > 30\n  ^^^^^^^^^^^");
+    }
+
+    #[test]
+    fn matches_json_records_by_their_own_type() {
+        let record = Object(json!({
+            "temperature": 31.5,
+            "station": "A01",
+            "count": "12",
+            "valid": true,
+            "flag": "false",
+            "note": null,
+            "tags": [1],
+        }));
+        let cases = [
+            ("temperature > 30", true),
+            // Text of an `any` field is read as the type it is compared with.
+            ("count > 9", true),
+            ("count == 12", true),
+            ("flag == false", true),
+            ("flag", false),
+            ("!flag", true),
+            ("station == \"A01\"", true),
+            ("station > 9", false),
+            ("station != 9", true),
+            ("valid && temperature < 40", true),
+            ("note == null", true),
+            ("missing == null", true),
+            ("missing == 1", false),
+            ("missing != 1", true),
+            ("missing < 1", false),
+            ("!(missing < 1)", true),
+            ("missing", false),
+            // The right side is not read once the left decides.
+            ("!valid && tags == 1", false),
+        ];
+        for (source, expected) in cases {
+            let filter = Filter::new(source, &Schema::default()).unwrap();
+            assert_eq!(filter.matches(&record), Ok(expected), "{}", source);
+        }
+
+        let errors = [
+            ("tags == 1", "column 1: field tags is an array, not a value\n  tags == 1\n  ^^^^"),
+            ("station", "column 1: \"A01\" is not true or false\n  station\n  ^^^^^^^"),
+            ("temperature", "column 1: 31.5 is not true or false\n  temperature\n  ^^^^^^^^^^^"),
+        ];
+        for (source, expected) in errors {
+            let filter = Filter::new(source, &Schema::default()).unwrap();
+            assert_eq!(filter.matches(&record).unwrap_err(), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let record = Object(json!({"a": true, "b": false, "c": false}));
+        let cases = [
+            ("a || b && c", true),
+            ("(a || b) && c", false),
+            ("b && c || a", true),
+            ("!a && b", false),
+            ("!(a && b)", true),
+            ("b || c || a", true),
+            ("a == true && b == false", true),
+        ];
+        for (source, expected) in cases {
+            let filter = Filter::new(source, &Schema::default()).unwrap();
+            assert_eq!(filter.matches(&record), Ok(expected), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Standard Library
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+
+// External Library
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+
+use crate::compression;
+use crate::placeholders::{self, Discovery, Values};
+use crate::protocols;
+use crate::text_encoding;
+use crate::utils;
+
+/// How the files matched for one slot are handed to the brewer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MatchMode {
+    /// Brew every matched file on its own and join the results
+    #[default]
+    Each,
+    /// Concatenate the matched files and brew them once
+    Group,
+}
+
+impl MatchMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchMode::Each => "each",
+            MatchMode::Group => "group",
+        }
+    }
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// The files of one slot that share the same placeholder values.
+#[derive(Clone, Debug)]
+pub struct SlotInput {
+    /// Known values plus those discovered from the matched paths.
+    pub values: Values,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Every input file of the slot starting at `dt`, grouped by placeholder
+/// values. The pattern is expanded for the slot and filled with the known
+/// `values` first; wildcards (`*`, `?`), character classes (`[0-9]`) and
+/// placeholders without a value are then matched against the tree, and
+/// matches are sorted.
+pub fn match_slot(base_url: &str, pattern: &str, dt: &DateTime<Tz>, values: &Values) -> Result<Vec<SlotInput>, String> {
+    let base_path = base_url.replace(protocols::FILE, "");
+    let expanded = utils::format_time(dt, pattern)?;
+    let discovered = placeholders::names(&placeholders::fill(&expanded, values, str::to_string)?);
+    if discovered.is_empty() && !is_glob(&expanded) {
+        let relative = placeholders::fill(&expanded, values, str::to_string)?;
+        return Ok(vec![SlotInput {
+            values: values.clone(),
+            paths: vec![PathBuf::from(format!("{}{}", base_path, relative))],
+        }]);
+    }
+    let relative = placeholders::fill(&expanded, values, glob::Pattern::escape)?;
+    let wildcards: Values = discovered.iter().map(|name| (name.clone(), "*".to_string())).collect();
+    let full = format!(
+        "{}{}",
+        glob::Pattern::escape(&base_path),
+        placeholders::fill(&relative, &wildcards, str::to_string)?
+    );
+    let discovery = Discovery::new(&relative)?;
+    let mut groups: BTreeMap<Values, Vec<PathBuf>> = BTreeMap::new();
+    for entry in glob::glob(&full).map_err(|e| format!("Invalid pattern {}: {}", full, e))? {
+        let path = match entry {
+            Ok(path) if path.is_file() => path,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Cannot read {}: {}", e.path().display(), e);
+                continue;
+            }
+        };
+        let relative_path = path.strip_prefix(&base_path).unwrap_or(&path).to_string_lossy().to_string();
+        match discovery.capture(&relative_path) {
+            Some(found) => {
+                let mut all = values.clone();
+                all.extend(found);
+                groups.entry(all).or_default().push(path);
+            }
+            None => debug!("{} does not fit the placeholders of {}", relative_path, relative),
+        }
+    }
+    if groups.is_empty() {
+        return Err(format!("No input matches {}", full));
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(values, mut paths)| {
+            paths.sort();
+            SlotInput { values, paths }
+        })
+        .collect())
+}
+
+/// Opens the matched files as one UTF-8 stream, in order, decompressing
+/// and decoding each from `charset` (or the detected encoding).
+pub fn open_group(paths: &[PathBuf], charset: Option<&'static Encoding>) -> Result<Box<dyn Read>, Box<dyn std::error::Error>> {
+    let mut reader: Box<dyn Read> = Box::new(std::io::empty());
+    for path in paths {
+        let file = compression::open(path)
+            .and_then(|file| Ok(text_encoding::decode(file, charset, &path.display().to_string())?))
+            .map_err(|e| {
+                eprintln!("Error opening file {}: {}", path.display(), e);
+                Box::<dyn std::error::Error>::from("Error: Unable to open file")
+            })?;
+        reader = Box::new(reader.chain(file));
+    }
+    Ok(reader)
+}
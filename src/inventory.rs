@@ -0,0 +1,164 @@
+// Standard Library
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// External Library
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+
+use crate::placeholders::{self, Values};
+use crate::protocols;
+use crate::slots::{self, Alignment, IntervalMode, SlotUnit};
+use crate::utils;
+
+/// The time slots actually present under a base URL, found by listing its
+/// tree and reading the pattern backwards.
+#[derive(Clone, Debug)]
+pub struct Inventory {
+    pub base_url: String,
+    pub pattern: String,
+    pub unit: SlotUnit,
+    /// Files that fit the pattern.
+    pub files: usize,
+    pub slots: BTreeSet<DateTime<Tz>>,
+    /// Zero-byte files that fit the pattern, with their slot.
+    pub empty_files: Vec<(DateTime<Tz>, PathBuf)>,
+}
+
+impl Inventory {
+    /// Lists the tree under `base_url` and keeps every file whose path fits
+    /// `pattern`, with the `values` of known placeholders filled in first and
+    /// the rest matching anything. Times are read in `tz`.
+    pub fn scan(base_url: &str, pattern: &str, tz: Tz, values: &Values) -> Result<Self, String> {
+        if !base_url.starts_with(protocols::FILE) {
+            return Err(format!("Cannot list {}: only {} is supported", base_url, protocols::FILE));
+        }
+        let unit = utils::extract_minimum_unit(pattern).ok_or_else(|| format!("Pattern {} has no time placeholder", pattern))?;
+        let filled = placeholders::fill(pattern, values, glob::Pattern::escape)?;
+        let (regex, groups) = placeholders::pattern_regex(&filled, true)?;
+        if !groups.iter().any(|g| g == "%Y") {
+            return Err(format!("Pattern {} has no %Y", pattern));
+        }
+        let base_path = base_url.replace(protocols::FILE, "");
+        let mut paths = Vec::new();
+        match walk(Path::new(&base_path), pattern.matches('/').count() + 1, &mut paths) {
+            Ok(()) => {}
+            // A store nothing was written to yet holds no slots.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Cannot list {}: {}", base_path, e)),
+        }
+        paths.sort();
+
+        let mut inventory = Inventory {
+            base_url: base_url.to_string(),
+            pattern: pattern.to_string(),
+            unit,
+            files: 0,
+            slots: BTreeSet::new(),
+            empty_files: Vec::new(),
+        };
+        for path in paths {
+            let relative = path.strip_prefix(&base_path).unwrap_or(&path).to_string_lossy().to_string();
+            let Some(fields) = placeholders::capture_groups(&regex, &groups, &relative) else {
+                continue;
+            };
+            match slot_of(&fields, tz) {
+                Some(dt) => {
+                    inventory.files += 1;
+                    inventory.slots.insert(dt);
+                    if fs::metadata(&path).is_ok_and(|m| m.len() == 0) {
+                        inventory.empty_files.push((dt, path));
+                    }
+                }
+                None => debug!("{} does not name a valid time", relative),
+            }
+        }
+        Ok(inventory)
+    }
+
+    /// First and last slot present.
+    pub fn range(&self) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        Some((*self.slots.first()?, *self.slots.last()?))
+    }
+
+    /// Runs of missing slots between the first and last present one, as
+    /// (first missing, last missing, count).
+    pub fn gaps(&self) -> Vec<(DateTime<Tz>, DateTime<Tz>, usize)> {
+        let Some((first, last)) = self.range() else {
+            return Vec::new();
+        };
+        let expected = slots::slots(first, last, self.unit, IntervalMode::Closed, Alignment::None);
+        missing_runs(&expected, &self.slots)
+    }
+}
+
+/// Runs of consecutive `expected` slots that are not `present`, as
+/// (first missing, last missing, count).
+pub fn missing_runs(
+    expected: &[DateTime<Tz>],
+    present: &BTreeSet<DateTime<Tz>>,
+) -> Vec<(DateTime<Tz>, DateTime<Tz>, usize)> {
+    let mut runs = Vec::new();
+    let mut open: Option<(DateTime<Tz>, DateTime<Tz>, usize)> = None;
+    for &dt in expected {
+        if present.contains(&dt) {
+            runs.extend(open.take());
+        } else {
+            open = Some(open.map_or((dt, dt, 1), |(start, _, n)| (start, dt, n + 1)));
+        }
+    }
+    runs.extend(open);
+    runs
+}
+
+fn walk(dir: &Path, depth: usize, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if depth == 0 {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Err(e) = walk(&path, depth - 1, paths) {
+                warn!("Cannot list {}: {}", path.display(), e);
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Start of the slot named by the captured time fields.
+fn slot_of(fields: &Values, tz: Tz) -> Option<DateTime<Tz>> {
+    let field = |specifier: &str, default: u32| fields.get(specifier).map_or(Some(default), |v| v.parse().ok());
+    let year: i32 = fields.get("%Y")?.parse().ok()?;
+    let naive = NaiveDate::from_ymd_opt(year, field("%m", 1)?, field("%d", 1)?)?
+        .and_hms_opt(field("%H", 0)?, field("%M", 0)?, field("%S", 0)?)?;
+    Some(slots::in_zone(&tz, naive))
+}
+
+impl fmt::Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}: {} file(s) in {} {} slot(s)",
+            self.base_url,
+            self.pattern,
+            self.files,
+            self.slots.len(),
+            self.unit.as_str()
+        )?;
+        let Some((first, last)) = self.range() else {
+            return Ok(());
+        };
+        write!(f, "\nrange: {} .. {}", first.to_rfc3339(), last.to_rfc3339())?;
+        let gaps = self.gaps();
+        write!(f, "\ngaps: {}", gaps.len())?;
+        for (start, end, n) in gaps {
+            write!(f, "\n  {} .. {} ({} slot(s))", start.to_rfc3339(), end.to_rfc3339(), n)?;
+        }
+        Ok(())
+    }
+}
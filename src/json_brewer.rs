@@ -0,0 +1,693 @@
+// Standard Library
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+// External Library
+use chrono_tz::Tz;
+use dbp_schema::dbp_schema::RealWorldDataStructureInfo;
+use serde_json::Value;
+
+use crate::brewing_demand::BrewingArgument;
+use crate::compression;
+use crate::csv_brewer;
+use crate::filter_expr::{self, Datum, Filter, Record, Schema};
+
+/// Prefix of the `json_*` arguments; any of them selects this brewer.
+pub const ARG_PREFIX: &str = "json_";
+const ARG_ROOT: &str = "json_root";
+const ARG_FIELDS: &str = "json_fields";
+const ARG_EXPLODE: &str = "json_explode";
+const ARG_FLATTEN: &str = "json_flatten";
+const ARG_OUTPUT: &str = "json_output";
+
+/// Input file extensions read as JSON, JSON Lines or concatenated JSON.
+pub const EXTENSIONS: &[&str] = &[".json", ".jsonl", ".ndjson"];
+
+/// Whether a pattern names JSON files.
+pub fn is_json(pattern: &str) -> bool {
+    let pattern = compression::strip_extension(pattern);
+    EXTENSIONS.iter().any(|extension| pattern.ends_with(extension))
+}
+
+/// Whether any `json_*` argument is given.
+pub fn is_requested(arguments: &[BrewingArgument]) -> bool {
+    arguments.iter().any(|arg| arg.key.starts_with(ARG_PREFIX))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    /// From the end when negative.
+    Index(i64),
+    Wildcard,
+}
+
+fn position(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len.checked_sub(index.unsigned_abs() as usize)? } else { index as usize };
+    (index < len).then_some(index)
+}
+
+fn quoted(text: &str) -> Option<&str> {
+    ['\'', '"']
+        .iter()
+        .find_map(|&quote| text.strip_prefix(quote)?.strip_suffix(quote))
+}
+
+/// A path into a JSON value, JSONPath-like (`$.readings[*].value`,
+/// `$['max temp']`, `station.id`, `readings.0`, `items[-1]`) or a JSON
+/// Pointer (`/station/id`).
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = |message: String| format!("path {:?}: {}", text, message);
+        let text = text.trim();
+        if let Some(pointer) = text.strip_prefix('/') {
+            let steps = pointer
+                .split('/')
+                .map(|token| Step::Key(token.replace("~1", "/").replace("~0", "~")))
+                .collect();
+            return Ok(JsonPath { steps });
+        }
+        let mut steps = Vec::new();
+        let mut rest = text.strip_prefix('$').unwrap_or(text);
+        let mut first = rest.len() == text.len();
+        while !rest.is_empty() {
+            if let Some(bracket) = rest.strip_prefix('[') {
+                let end = bracket.find(']').ok_or_else(|| error("missing ]".to_string()))?;
+                let inner = bracket[..end].trim();
+                steps.push(if inner == "*" {
+                    Step::Wildcard
+                } else if let Some(key) = quoted(inner) {
+                    Step::Key(key.to_string())
+                } else {
+                    Step::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| error(format!("[{}] is not an index, * or a quoted key", inner)))?,
+                    )
+                });
+                rest = &bracket[end + 1..];
+            } else {
+                let segment = match rest.strip_prefix('.') {
+                    Some(segment) => segment,
+                    None if first => rest,
+                    None => return Err(error(format!("unexpected {:?}", rest))),
+                };
+                let len = segment.find(['.', '[']).unwrap_or(segment.len());
+                steps.push(match &segment[..len] {
+                    "" => return Err(error("empty field name".to_string())),
+                    "*" => Step::Wildcard,
+                    name => Step::Key(name.to_string()),
+                });
+                rest = &segment[len..];
+            }
+            first = false;
+        }
+        Ok(JsonPath { steps })
+    }
+
+    /// Whether the path reaches at most one value.
+    pub fn is_singular(&self) -> bool {
+        !self.steps.contains(&Step::Wildcard)
+    }
+
+    /// Every value the path reaches, in document order.
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut selected = vec![value];
+        for step in &self.steps {
+            selected = selected
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (step, value) {
+                        (Step::Key(key), Value::Object(map)) => map.get(key).into_iter().collect(),
+                        (Step::Key(key), Value::Array(items)) => {
+                            key.parse::<usize>().ok().and_then(|i| items.get(i)).into_iter().collect()
+                        }
+                        (Step::Index(i), Value::Array(items)) => {
+                            position(items.len(), *i).map(|i| &items[i]).into_iter().collect()
+                        }
+                        (Step::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Step::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        selected
+    }
+
+    /// The value at a singular path, or an array of every value a path
+    /// with wildcards reaches. Missing values are null.
+    pub fn value(&self, record: &Value) -> Value {
+        let selected = self.select(record);
+        if self.is_singular() {
+            selected.first().map_or(Value::Null, |&value| value.clone())
+        } else {
+            Value::Array(selected.into_iter().cloned().collect())
+        }
+    }
+
+    fn get_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        self.steps.iter().try_fold(value, |value, step| match (step, value) {
+            (Step::Key(key), Value::Object(map)) => map.get_mut(key),
+            (Step::Key(key), Value::Array(items)) => items.get_mut(key.parse::<usize>().ok()?),
+            (Step::Index(i), Value::Array(items)) => {
+                let i = position(items.len(), *i)?;
+                items.get_mut(i)
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Looks up a field not described by the structure info by its name as a
+/// dotted path (`station.id`, `readings.0`).
+fn lookup<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    name.split('.').try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// How brewed records are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// One JSON value per line
+    Lines,
+    /// One JSON array of every record
+    Array,
+    /// CSV or TSV with a header row, by delimiter
+    Csv(u8),
+}
+
+impl OutputFormat {
+    fn parse(key: &str, value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "jsonl" | "ndjson" | "lines" => Ok(OutputFormat::Lines),
+            "json" | "array" => Ok(OutputFormat::Array),
+            "csv" => Ok(OutputFormat::Csv(b',')),
+            "tsv" => Ok(OutputFormat::Csv(b'\t')),
+            v => Err(format!("{} {:?} is not jsonl, array, csv or tsv", key, v)),
+        }
+    }
+
+    /// The format named by the output pattern's extension; JSON Lines
+    /// unless it is `.json`, `.csv` or `.tsv`.
+    fn for_pattern(pattern: &str) -> Self {
+        let pattern = compression::strip_extension(pattern);
+        if pattern.ends_with(".json") {
+            OutputFormat::Array
+        } else if pattern.ends_with(".csv") {
+            OutputFormat::Csv(b',')
+        } else if pattern.ends_with(".tsv") {
+            OutputFormat::Csv(b'\t')
+        } else {
+            OutputFormat::Lines
+        }
+    }
+}
+
+/// Where the records of one JSON input are and how they are written.
+#[derive(Clone, Debug)]
+pub struct JsonConfig {
+    /// Where the records are in each value read; the value itself, or the
+    /// elements of a top-level array, when `None`.
+    root: Option<JsonPath>,
+    /// Output fields by name, in order; records are written whole when empty.
+    fields: Vec<(String, JsonPath)>,
+    /// Arrays to unnest, in order: a record is repeated for each element.
+    explode: Vec<JsonPath>,
+    /// Write nested objects as `parent.child` fields.
+    flatten: bool,
+    output: OutputFormat,
+    /// Records to keep, from the `filter` argument.
+    filter: Option<Filter>,
+    /// Paths of the described fields by name.
+    paths: HashMap<String, JsonPath>,
+}
+
+impl JsonConfig {
+    /// Fields default to the structure items, each read from its
+    /// `dbp:structurePath` (or its name) and written under its name.
+    pub fn new(
+        arguments: &[BrewingArgument],
+        structure: Option<&RealWorldDataStructureInfo>,
+        output_pattern: &str,
+        time_zone: Tz,
+    ) -> Result<Self, String> {
+        let mut described = Vec::new();
+        for (i, item) in structure.map(|s| s.structure_items.as_slice()).unwrap_or_default().iter().enumerate() {
+            let Some(name) = item.name.clone().or_else(|| item.structure_path.clone()) else {
+                warn!("Structure item {} has neither a name nor a path; ignoring it", i + 1);
+                continue;
+            };
+            let path = JsonPath::parse(item.structure_path.as_deref().unwrap_or(&name))
+                .map_err(|e| format!("structure item {}: {}", name, e))?;
+            described.push((name, path));
+        }
+        let paths: HashMap<String, JsonPath> = described.iter().cloned().collect();
+        // A described field by name, or else a path.
+        let field = |key: &str, text: &str| -> Result<JsonPath, String> {
+            match paths.get(text) {
+                Some(path) => Ok(path.clone()),
+                None => JsonPath::parse(text).map_err(|e| format!("{} {}", key, e)),
+            }
+        };
+        let mut config = JsonConfig {
+            root: None,
+            fields: described.clone(),
+            explode: Vec::new(),
+            flatten: false,
+            output: OutputFormat::for_pattern(output_pattern),
+            filter: None,
+            paths: HashMap::new(),
+        };
+        let mut flatten = None;
+        for arg in arguments {
+            let (key, value) = (arg.key.as_str(), arg.value.as_str());
+            match key {
+                ARG_ROOT => config.root = Some(JsonPath::parse(value).map_err(|e| format!("{} {}", key, e))?),
+                ARG_FIELDS => {
+                    config.fields = entries(value)
+                        .into_iter()
+                        .map(|entry| match named(&entry) {
+                            Some((name, text)) => Ok((name.trim().to_string(), field(key, text.trim())?)),
+                            None => Ok((entry.clone(), field(key, &entry)?)),
+                        })
+                        .collect::<Result<_, String>>()?;
+                }
+                ARG_EXPLODE => {
+                    for text in entries(value) {
+                        let path = field(key, &text)?;
+                        if !path.is_singular() {
+                            return Err(format!("{} path {:?} must not have wildcards", key, text));
+                        }
+                        config.explode.push(path);
+                    }
+                }
+                ARG_FLATTEN => flatten = Some(csv_brewer::boolean(key, value)?),
+                ARG_OUTPUT => config.output = OutputFormat::parse(key, value)?,
+                key if key.starts_with(ARG_PREFIX) => return Err(format!("unknown brewing argument {}", key)),
+                _ => {}
+            }
+        }
+        // CSV cells cannot hold objects, so CSV is flattened unless asked not to be.
+        config.flatten = flatten.unwrap_or(matches!(config.output, OutputFormat::Csv(_)));
+        config.filter = filter_expr::from_arguments(arguments, &Schema::from_structure(structure, time_zone))?;
+        config.paths = paths;
+        Ok(config)
+    }
+
+    /// Only filters records, writing the kept ones whole.
+    pub fn for_filter(
+        arguments: &[BrewingArgument],
+        structure: Option<&RealWorldDataStructureInfo>,
+        output_pattern: &str,
+        time_zone: Tz,
+    ) -> Result<Self, String> {
+        let config = JsonConfig { fields: Vec::new(), ..Self::new(arguments, structure, output_pattern, time_zone)? };
+        if config.filter.is_none() {
+            return Err(format!("no {} argument", filter_expr::ARG_FILTER));
+        }
+        Ok(config)
+    }
+
+    /// The records in one value read, after unnesting.
+    fn records(&self, value: Value) -> Vec<Value> {
+        let mut records = match &self.root {
+            None => match value {
+                Value::Array(items) => items,
+                value => vec![value],
+            },
+            Some(root) => match root.select(&value).as_slice() {
+                [Value::Array(items)] if root.is_singular() => items.clone(),
+                selected => selected.iter().map(|&value| value.clone()).collect(),
+            },
+        };
+        for path in &self.explode {
+            records = records.into_iter().flat_map(|record| explode(record, path)).collect();
+        }
+        records
+    }
+
+    /// A kept record as it is written.
+    fn shape(&self, record: Value) -> Shaped {
+        if !self.fields.is_empty() {
+            let fields = self.fields.iter().map(|(name, path)| (name.clone(), path.value(&record)));
+            return Shaped::Fields(if self.flatten { flatten(fields) } else { fields.collect() });
+        }
+        match record {
+            Value::Object(map) if self.flatten => Shaped::Fields(flatten(map)),
+            record => Shaped::Whole(record),
+        }
+    }
+}
+
+/// One record per element of the array at `path`, with the element in
+/// place of the array. Records without an array there are kept as they
+/// are; an empty array leaves no record.
+fn explode(record: Value, path: &JsonPath) -> Vec<Value> {
+    let items = match path.select(&record).first() {
+        Some(Value::Array(items)) => items.clone(),
+        _ => return vec![record],
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            let mut exploded = record.clone();
+            if let Some(slot) = path.get_mut(&mut exploded) {
+                *slot = item;
+            }
+            exploded
+        })
+        .collect()
+}
+
+fn flatten(fields: impl IntoIterator<Item = (String, Value)>) -> Vec<(String, Value)> {
+    let mut flat = Vec::new();
+    for (name, value) in fields {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                flat.extend(flatten(map).into_iter().map(|(key, value)| (format!("{}.{}", name, key), value)));
+            }
+            value => flat.push((name, value)),
+        }
+    }
+    flat
+}
+
+/// Splits a list of paths on the commas outside brackets and quotes, so
+/// that `$['a,b']` stays one entry.
+fn entries(value: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let (mut depth, mut quote, mut start) = (0usize, None, 0);
+    for (i, c) in value.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                entries.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&value[start..]);
+    entries.into_iter().map(str::trim).filter(|entry| !entry.is_empty()).map(String::from).collect()
+}
+
+/// Splits a `name=path` field entry. Names may hold `:` (`schema:name`);
+/// an `=` inside a bracketed key (`$['a=b']`) is part of the path.
+fn named(entry: &str) -> Option<(&str, &str)> {
+    entry.split_once('=').filter(|(name, _)| !name.contains('['))
+}
+
+/// A record as it is written.
+enum Shaped {
+    /// Fields in output order
+    Fields(Vec<(String, Value)>),
+    /// A value written as read
+    Whole(Value),
+}
+
+/// A record as seen by the filter.
+struct JsonRecord<'a> {
+    value: &'a Value,
+    paths: &'a HashMap<String, JsonPath>,
+}
+
+impl Record for JsonRecord<'_> {
+    fn field(&self, name: &str) -> Datum<'_> {
+        let value = match self.paths.get(name) {
+            Some(path) => path.select(self.value).first().copied(),
+            None => lookup(self.value, name),
+        };
+        value.map_or(Datum::Missing, Datum::Json)
+    }
+}
+
+/// Writes the records brewed from every file of a slot.
+pub struct RecordWriter {
+    output: OutputFormat,
+    text: String,
+    records: usize,
+    csv: Option<csv::Writer<Vec<u8>>>,
+    /// Every field of the CSV rows, in the order first seen.
+    header: Vec<String>,
+    /// CSV rows, held until `finish` so that no field is left out of the header.
+    rows: Vec<HashMap<String, Value>>,
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+impl RecordWriter {
+    pub fn new(config: &JsonConfig) -> Self {
+        let csv = match config.output {
+            OutputFormat::Csv(delimiter) => Some(csv::WriterBuilder::new().delimiter(delimiter).from_writer(Vec::new())),
+            _ => None,
+        };
+        RecordWriter { output: config.output, text: String::new(), records: 0, csv, header: Vec::new(), rows: Vec::new() }
+    }
+
+    fn write(&mut self, record: Shaped) -> Result<(), Box<dyn Error>> {
+        self.records += 1;
+        if self.csv.is_some() {
+            let fields = match record {
+                Shaped::Fields(fields) => fields,
+                Shaped::Whole(Value::Object(map)) => map.into_iter().collect(),
+                Shaped::Whole(value) => vec![("value".to_string(), value)],
+            };
+            for (name, _) in &fields {
+                if !self.header.contains(name) {
+                    self.header.push(name.clone());
+                }
+            }
+            self.rows.push(fields.into_iter().collect());
+            return Ok(());
+        }
+        let json = match record {
+            Shaped::Whole(value) => serde_json::to_string(&value)?,
+            // Written by hand to keep the fields in order.
+            Shaped::Fields(fields) => {
+                let mut entries = Vec::with_capacity(fields.len());
+                for (name, value) in &fields {
+                    entries.push(format!("{}:{}", serde_json::to_string(name)?, serde_json::to_string(value)?));
+                }
+                format!("{{{}}}", entries.join(","))
+            }
+        };
+        match self.output {
+            OutputFormat::Array => {
+                self.text.push_str(if self.records == 1 { "[\n" } else { ",\n" });
+                self.text.push_str(&json);
+            }
+            _ => {
+                self.text.push_str(&json);
+                self.text.push('\n');
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<String, Box<dyn Error>> {
+        if let Some(mut writer) = self.csv {
+            if !self.header.is_empty() {
+                writer.write_record(&self.header)?;
+                for mut row in self.rows {
+                    writer.write_record(self.header.iter().map(|name| row.remove(name).as_ref().map(cell).unwrap_or_default()))?;
+                }
+            }
+            return Ok(String::from_utf8(writer.into_inner()?)?);
+        }
+        if self.output == OutputFormat::Array {
+            self.text.push_str(if self.records == 0 { "[]\n" } else { "\n]\n" });
+        }
+        Ok(self.text)
+    }
+}
+
+/// Brews the records of one input, read as a stream of JSON values: one
+/// per line (JSON Lines) or one after another. Each value is taken apart
+/// into records, which are unnested, filtered and reshaped one by one.
+pub fn brew(file: &mut dyn Read, config: &JsonConfig, writer: &mut RecordWriter) -> Result<(), Box<dyn Error>> {
+    let mut count = 0;
+    for value in serde_json::Deserializer::from_reader(file).into_iter::<Value>() {
+        for record in config.records(value?) {
+            count += 1;
+            if let Some(filter) = &config.filter {
+                let kept = filter
+                    .matches(&JsonRecord { value: &record, paths: &config.paths })
+                    .map_err(|e| format!("record {}: {}", count, e))?;
+                if !kept {
+                    continue;
+                }
+            }
+            writer.write(config.shape(record))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbp_schema::dbp_schema::RealWorldDataStructureItem;
+    use serde_json::json;
+
+    fn arguments(pairs: &[(&str, &str)]) -> Vec<BrewingArgument> {
+        pairs.iter().map(|(key, value)| BrewingArgument { key: key.to_string(), value: value.to_string() }).collect()
+    }
+
+    fn run(input: &str, arguments: &[(&str, &str)], structure: Option<&RealWorldDataStructureInfo>, output_pattern: &str) -> String {
+        let config = JsonConfig::new(&self::arguments(arguments), structure, output_pattern, Tz::Asia__Tokyo).unwrap();
+        let mut writer = RecordWriter::new(&config);
+        brew(&mut input.as_bytes(), &config, &mut writer).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn selects_values_by_path() {
+        let value = json!({
+            "station": {"id": "s1", "max temp": 30},
+            "readings": [{"value": 1}, {"value": 2}, {"value": 3}],
+            "a/b": {"~c": true},
+            "a,b": "comma",
+        });
+        let cases = [
+            ("$.station.id", json!("s1")),
+            ("station.id", json!("s1")),
+            ("$['station']['max temp']", json!(30)),
+            ("$[\"station\"].id", json!("s1")),
+            ("readings.1.value", json!(2)),
+            ("$.readings[-1].value", json!(3)),
+            ("$.readings[*].value", json!([1, 2, 3])),
+            ("$.readings.*.value", json!([1, 2, 3])),
+            ("/a~1b/~0c", json!(true)),
+            ("$['a,b']", json!("comma")),
+            ("$.missing", Value::Null),
+            ("$.readings[5]", Value::Null),
+            ("$", value.clone()),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(JsonPath::parse(text).unwrap().value(&value), expected, "{}", text);
+        }
+        for text in ["$.readings[", "$.readings[x]", "$..a", "$.a b[0]x"] {
+            assert!(JsonPath::parse(text).is_err(), "{}", text);
+        }
+        assert!(JsonPath::parse("$.a[0]").unwrap().is_singular());
+        assert!(!JsonPath::parse("$.a[*]").unwrap().is_singular());
+    }
+
+    #[test]
+    fn splits_entries_outside_brackets_and_quotes() {
+        let cases = [
+            ("a, b ,,c", vec!["a", "b", "c"]),
+            ("$['a,b'],c", vec!["$['a,b']", "c"]),
+            ("x=$[\"a,]b\"], y=$.c", vec!["x=$[\"a,]b\"]", "y=$.c"]),
+            ("$.a[0,1]", vec!["$.a[0,1]"]),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(entries(value), expected, "{}", value);
+        }
+        assert_eq!(named("schema:name=$.a"), Some(("schema:name", "$.a")));
+        assert_eq!(named("$['a=b']"), None);
+    }
+
+    #[test]
+    fn explodes_arrays_in_place() {
+        let path = JsonPath::parse("$.items").unwrap();
+        let cases = [
+            (json!({"id": 1, "items": [10, 20]}), vec![json!({"id": 1, "items": 10}), json!({"id": 1, "items": 20})]),
+            (json!({"id": 2, "items": []}), vec![]),
+            (json!({"id": 3, "items": "one"}), vec![json!({"id": 3, "items": "one"})]),
+            (json!({"id": 4}), vec![json!({"id": 4})]),
+        ];
+        for (record, expected) in cases {
+            assert_eq!(explode(record.clone(), &path), expected, "{}", record);
+        }
+    }
+
+    #[test]
+    fn flattens_nested_objects() {
+        let fields = vec![
+            ("a".to_string(), json!({"b": {"c": 1}, "d": [1, 2]})),
+            ("e".to_string(), json!({})),
+            ("f".to_string(), json!(null)),
+        ];
+        assert_eq!(
+            flatten(fields),
+            vec![
+                ("a.b.c".to_string(), json!(1)),
+                ("a.d".to_string(), json!([1, 2])),
+                ("e".to_string(), json!({})),
+                ("f".to_string(), json!(null)),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_lines_arrays_and_csv() {
+        let input = r#"{"id": 1, "pos": {"x": 1, "y": 2}} {"id": 2, "pos": {"x": 3, "y": 4}}"#;
+        let cases = [
+            (vec![], "out.jsonl", "{\"id\":1,\"pos\":{\"x\":1,\"y\":2}}\n{\"id\":2,\"pos\":{\"x\":3,\"y\":4}}\n"),
+            (vec![("json_fields", "pos.y,id")], "out.json", "[\n{\"pos.y\":2,\"id\":1},\n{\"pos.y\":4,\"id\":2}\n]\n"),
+            (vec![("json_fields", "n=id"), ("filter", "id > 1")], "out.json", "[\n{\"n\":2}\n]\n"),
+            (vec![("filter", "id > 5")], "out.json", "[]\n"),
+            (vec![], "out.csv", "id,pos.x,pos.y\n1,1,2\n2,3,4\n"),
+            (vec![("json_flatten", "false")], "out.tsv", "id\tpos\n1\t\"{\"\"x\"\":1,\"\"y\"\":2}\"\n2\t\"{\"\"x\"\":3,\"\"y\"\":4}\"\n"),
+            (vec![("json_output", "jsonl"), ("json_flatten", "true")], "out.csv", "{\"id\":1,\"pos.x\":1,\"pos.y\":2}\n{\"id\":2,\"pos.x\":3,\"pos.y\":4}\n"),
+        ];
+        for (arguments, output_pattern, expected) in cases {
+            assert_eq!(run(input, &arguments, None, output_pattern), expected, "{:?} {}", arguments, output_pattern);
+        }
+    }
+
+    #[test]
+    fn unions_the_csv_header_over_every_record() {
+        let input = "{\"a\": 1}\n{\"b\": \"x\"}\n{\"a\": null, \"c\": [1]}\n\"bare\"\n";
+        assert_eq!(run(input, &[], None, "out.csv"), "a,b,c,value\n1,,,\n,x,,\n,,[1],\n,,,bare\n");
+        assert_eq!(run("", &[], None, "out.csv"), "");
+    }
+
+    #[test]
+    fn reads_fields_and_roots_from_structure_and_arguments() {
+        let structure = RealWorldDataStructureInfo {
+            structure_items: vec![
+                RealWorldDataStructureItem {
+                    name: Some("temp".to_string()),
+                    structure_path: Some("$['max temp']".to_string()),
+                    ..Default::default()
+                },
+                RealWorldDataStructureItem { name: Some("station".to_string()), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let input = r#"{"data": {"rows": [{"station": "s1", "max temp": 30, "a,b": 1}, {"station": "s2", "max temp": 25, "a,b": 2}]}}"#;
+        let cases = [
+            (vec![("json_root", "$.data.rows")], "{\"temp\":30,\"station\":\"s1\"}\n{\"temp\":25,\"station\":\"s2\"}\n"),
+            (vec![("json_root", "$.data.rows"), ("filter", "temp < 28"), ("json_fields", "station")], "{\"station\":\"s2\"}\n"),
+            (vec![("json_root", "$.data.rows[*]"), ("json_fields", "ab=$['a,b'],temp")], "{\"ab\":1,\"temp\":30}\n{\"ab\":2,\"temp\":25}\n"),
+        ];
+        for (arguments, expected) in cases {
+            assert_eq!(run(input, &arguments, Some(&structure), "out.jsonl"), expected, "{:?}", arguments);
+        }
+        let error = JsonConfig::new(&arguments(&[("json_explode", "$.a[*]")]), None, "out.jsonl", Tz::UTC).unwrap_err();
+        assert!(error.contains("wildcards"), "{}", error);
+        assert!(JsonConfig::new(&arguments(&[("json_unknown", "x")]), None, "out.jsonl", Tz::UTC).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+// Standard Library
+use std::collections::HashMap;
+
+// External Library
+use async_recursion::async_recursion;
+use serde_json::{Map, Value};
+
+use crate::ref_resolver::RefResolver;
+
+pub const DBP_IRI: &str = "https://exdata.co.jp/dbp/schema/";
+pub const SCHEMA_IRI: &str = "https://schema.org/";
+
+/// Prefixes the brewer reads keys with (`dbp:distribution`, `schema:name`),
+/// with every IRI that is compacted to each of them.
+const CANONICAL_PREFIXES: [(&str, &[&str]); 2] = [
+    ("dbp", &[DBP_IRI]),
+    ("schema", &[SCHEMA_IRI, "http://schema.org/"]),
+];
+
+/// How many remote `@context`s may be chained through each other.
+const MAX_CONTEXT_DEPTH: usize = 8;
+
+/// Keys whose string values are IRIs and get the same treatment as keys.
+const IRI_VALUED_KEYS: [&str; 1] = ["@type"];
+
+/// The `@context` that normalized documents carry.
+pub fn canonical_context() -> Value {
+    let mut context = Map::new();
+    for (prefix, iris) in CANONICAL_PREFIXES {
+        context.insert(prefix.to_string(), Value::from(iris[0]));
+    }
+    Value::Object(context)
+}
+
+/// Term and prefix definitions in scope at some node.
+#[derive(Clone, Debug)]
+struct ActiveContext {
+    terms: HashMap<String, String>,
+    vocab: Option<String>,
+}
+
+impl Default for ActiveContext {
+    /// Documents without a `@context` are read with the canonical prefixes.
+    fn default() -> Self {
+        let terms = CANONICAL_PREFIXES
+            .iter()
+            .map(|(prefix, iris)| (prefix.to_string(), iris[0].to_string()))
+            .collect();
+        ActiveContext { terms, vocab: None }
+    }
+}
+
+impl ActiveContext {
+    /// Applies a local, remote or array `@context` on top of this one.
+    #[async_recursion]
+    async fn extend(&mut self, context: &Value, resolver: &RefResolver, depth: usize) -> Result<(), String> {
+        match context {
+            Value::Null => *self = ActiveContext::default(),
+            Value::String(url) => {
+                if depth == 0 {
+                    return Err(format!("@context {} is nested too deeply", url));
+                }
+                let remote = resolver
+                    .fetch_raw(url)
+                    .await
+                    .and_then(|document| document.get("@context").cloned().ok_or_else(|| format!("{} has no @context", url)));
+                match remote {
+                    Ok(remote) => self.extend(&remote, resolver, depth - 1).await?,
+                    Err(e) => self.fall_back(url, &e),
+                }
+            }
+            Value::Array(contexts) => {
+                for context in contexts {
+                    self.extend(context, resolver, depth).await?;
+                }
+            }
+            Value::Object(definitions) => {
+                if let Some(vocab) = definitions.get("@vocab") {
+                    self.vocab = vocab.as_str().map(String::from);
+                }
+                let mut defined = Vec::new();
+                for (term, definition) in definitions {
+                    if term.starts_with('@') {
+                        continue;
+                    }
+                    let iri = match definition {
+                        Value::String(iri) => iri.as_str(),
+                        Value::Object(d) => match d.get("@id").and_then(|v| v.as_str()) {
+                            Some(iri) => iri,
+                            None => continue,
+                        },
+                        Value::Null => {
+                            self.terms.remove(term);
+                            continue;
+                        }
+                        _ => return Err(format!("invalid definition of term {:?} in @context", term)),
+                    };
+                    // Kept as written until the whole object is read:
+                    // definitions may refer to prefixes defined later in it.
+                    self.terms.insert(term.clone(), iri.to_string());
+                    defined.push(term);
+                }
+                // Expanded now, so that a later context redefining a prefix
+                // does not change the meaning of terms defined with it.
+                let expanded: Vec<(String, String)> =
+                    defined.into_iter().map(|term| (term.clone(), self.expand_iri(&self.terms[term]))).collect();
+                self.terms.extend(expanded);
+            }
+            _ => return Err("@context must be a URL, an object or an array".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Keeps reading a document whose remote `@context` is unavailable with
+    /// the canonical prefixes in scope. A context named after one of their
+    /// namespaces, such as `https://schema.org`, is taken to make it the
+    /// vocabulary, which is what those contexts do.
+    fn fall_back(&mut self, url: &str, error: &str) {
+        let namespace = CANONICAL_PREFIXES
+            .iter()
+            .flat_map(|(_, iris)| iris.iter())
+            .find(|iri| iri.trim_end_matches('/') == url.trim_end_matches('/'));
+        match namespace {
+            Some(namespace) => {
+                warn!("Cannot load @context {} ({}); using {} as the vocabulary", url, error, namespace);
+                self.vocab = Some(namespace.to_string());
+            }
+            None => warn!("Cannot load @context {} ({}); reading keys with the canonical prefixes only", url, error),
+        }
+        for (prefix, iris) in CANONICAL_PREFIXES {
+            self.terms.entry(prefix.to_string()).or_insert_with(|| iris[0].to_string());
+        }
+    }
+
+    /// Expands a term, compact IRI (`prefix:suffix`) or absolute IRI.
+    fn expand_iri(&self, value: &str) -> String {
+        self.expand_iri_within(value, MAX_CONTEXT_DEPTH)
+    }
+
+    fn expand_iri_within(&self, value: &str, depth: usize) -> String {
+        if value.starts_with('@') || depth == 0 {
+            return value.to_string();
+        }
+        if let Some(iri) = self.terms.get(value) {
+            if iri != value {
+                return self.expand_iri_within(iri, depth - 1);
+            }
+        }
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if suffix.starts_with("//") {
+                return value.to_string();
+            }
+            if let Some(iri) = self.terms.get(prefix) {
+                return format!("{}{}", self.expand_iri_within(iri, depth - 1), suffix);
+            }
+            return value.to_string();
+        }
+        match &self.vocab {
+            Some(vocab) => format!("{}{}", self.expand_iri_within(vocab, depth - 1), value),
+            None => value.to_string(),
+        }
+    }
+
+    /// Compacts an expanded IRI to a canonical prefix. A prefix that the
+    /// document itself binds to another IRI is honoured as well.
+    fn compact_iri(&self, iri: &str) -> String {
+        for (prefix, iris) in CANONICAL_PREFIXES {
+            let declared = self.terms.get(prefix).map(|iri| self.expand_iri(iri));
+            for namespace in iris.iter().copied().chain(declared.as_deref()) {
+                if let Some(suffix) = iri.strip_prefix(namespace) {
+                    if !suffix.is_empty() {
+                        return format!("{}:{}", prefix, suffix);
+                    }
+                }
+            }
+        }
+        iri.to_string()
+    }
+
+    fn normalize_key(&self, key: &str) -> String {
+        self.compact_iri(&self.expand_iri(key))
+    }
+}
+
+/// Expands every key of a JSON-LD document against the `@context`s it
+/// declares and compacts the result to the canonical `dbp:` and `schema:`
+/// prefixes, so that the same terms are read however the producer spelled
+/// them. The returned document carries the canonical context.
+pub async fn normalize(document: &Value, resolver: &RefResolver) -> Result<Value, String> {
+    let mut normalized = normalize_node(document, &ActiveContext::default(), resolver).await?;
+    if let Value::Object(obj) = &mut normalized {
+        if document.get("@context").is_some() {
+            obj.insert("@context".to_string(), canonical_context());
+        }
+    }
+    Ok(normalized)
+}
+
+#[async_recursion]
+async fn normalize_node(
+    node: &Value,
+    context: &ActiveContext,
+    resolver: &RefResolver,
+) -> Result<Value, String> {
+    match node {
+        Value::Object(obj) => {
+            let mut context = context.clone();
+            if let Some(local) = obj.get("@context") {
+                context.extend(local, resolver, MAX_CONTEXT_DEPTH).await?;
+            }
+            let mut normalized = Map::new();
+            for (key, value) in obj {
+                if key == "@context" {
+                    continue;
+                }
+                let key = context.normalize_key(key);
+                let value = if IRI_VALUED_KEYS.contains(&key.as_str()) {
+                    match value {
+                        Value::String(iri) => Value::from(context.normalize_key(iri)),
+                        Value::Array(iris) => Value::Array(
+                            iris.iter()
+                                .map(|v| match v {
+                                    Value::String(iri) => Value::from(context.normalize_key(iri)),
+                                    v => v.clone(),
+                                })
+                                .collect(),
+                        ),
+                        v => v.clone(),
+                    }
+                } else {
+                    normalize_node(value, &context, resolver).await?
+                };
+                if normalized.insert(key.clone(), value).is_some() {
+                    warn!("{} appears more than once after @context expansion; keeping the last value", key);
+                }
+            }
+            Ok(Value::Object(normalized))
+        }
+        Value::Array(values) => {
+            let mut normalized = Vec::with_capacity(values.len());
+            for value in values {
+                normalized.push(normalize_node(value, context, resolver).await?);
+            }
+            Ok(Value::Array(normalized))
+        }
+        v => Ok(v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{headers, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn normalized(document: Value, resolver: &RefResolver) -> Value {
+        normalize(&document, resolver).await.unwrap()
+    }
+
+    fn offline() -> RefResolver {
+        RefResolver::new(None, None, true)
+    }
+
+    #[tokio::test]
+    async fn local_contexts() {
+        let cases = [
+            // Prefixes under other names.
+            (
+                json!({ "@context": { "d": DBP_IRI, "s": SCHEMA_IRI }, "d:baseUrl": "b", "s:name": "n" }),
+                json!({ "@context": canonical_context(), "dbp:baseUrl": "b", "schema:name": "n" }),
+            ),
+            // Terms, expanded definitions and the http schema.org namespace.
+            (
+                json!({
+                    "@context": { "name": "http://schema.org/name", "base": { "@id": "dbp:baseUrl" } },
+                    "name": "n",
+                    "base": "b",
+                }),
+                json!({ "@context": canonical_context(), "schema:name": "n", "dbp:baseUrl": "b" }),
+            ),
+            // Contexts apply to nested nodes and array members.
+            (
+                json!({ "@context": { "s": SCHEMA_IRI }, "s:distribution": [{ "s:name": "n" }] }),
+                json!({ "@context": canonical_context(), "schema:distribution": [{ "schema:name": "n" }] }),
+            ),
+            // Without a context the canonical prefixes are read as they are.
+            (json!({ "schema:name": "n", "other": 1 }), json!({ "schema:name": "n", "other": 1 })),
+        ];
+        for (document, expected) in cases {
+            assert_eq!(normalized(document.clone(), &offline()).await, expected, "{}", document);
+        }
+    }
+
+    #[tokio::test]
+    async fn array_contexts_apply_in_order() {
+        let document = json!({
+            "@context": [{ "s": SCHEMA_IRI }, { "title": "s:name" }, { "s": DBP_IRI }],
+            "title": "n",
+            "s:baseUrl": "b",
+        });
+        assert_eq!(
+            normalized(document, &offline()).await,
+            json!({ "@context": canonical_context(), "schema:name": "n", "dbp:baseUrl": "b" })
+        );
+    }
+
+    #[tokio::test]
+    async fn vocab_expands_plain_terms_and_types() {
+        let document = json!({ "@context": { "@vocab": SCHEMA_IRI }, "@type": "Dataset", "name": "n", "@id": "x" });
+        assert_eq!(
+            normalized(document, &offline()).await,
+            json!({ "@context": canonical_context(), "@type": "schema:Dataset", "schema:name": "n", "@id": "x" })
+        );
+    }
+
+    #[tokio::test]
+    async fn null_resets_the_context() {
+        let document = json!({
+            "@context": { "@vocab": SCHEMA_IRI, "d": DBP_IRI },
+            "name": "outer",
+            "dbp:brewerOutputStore": { "@context": null, "name": "inner", "d:pattern": "p", "dbp:baseUrl": "b" },
+            "dbp:brewerInfo": { "@context": { "d": null }, "d:pattern": "p" },
+        });
+        assert_eq!(
+            normalized(document, &offline()).await,
+            json!({
+                "@context": canonical_context(),
+                "schema:name": "outer",
+                "dbp:brewerOutputStore": { "name": "inner", "d:pattern": "p", "dbp:baseUrl": "b" },
+                "dbp:brewerInfo": { "d:pattern": "p" },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_remote_contexts_fall_back_to_canonical_prefixes() {
+        let cases = [
+            (
+                json!({ "@context": "https://example.com/context.jsonld", "schema:name": "n", "name": "m" }),
+                json!({ "@context": canonical_context(), "schema:name": "n", "name": "m" }),
+            ),
+            (
+                json!({ "@context": "https://schema.org", "name": "n", "dbp:pattern": "p" }),
+                json!({ "@context": canonical_context(), "schema:name": "n", "dbp:pattern": "p" }),
+            ),
+            (
+                json!({ "@context": ["https://schema.org/", { "d": DBP_IRI }], "d:pattern": "p" }),
+                json!({ "@context": canonical_context(), "dbp:pattern": "p" }),
+            ),
+        ];
+        for (document, expected) in cases {
+            assert_eq!(normalized(document.clone(), &offline()).await, expected, "{}", document);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_contexts_are_errors() {
+        for context in [json!(1), json!({ "name": 1 })] {
+            assert!(normalize(&json!({ "@context": context }), &offline()).await.is_err(), "{}", context);
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_contexts_are_negotiated_and_followed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/context"))
+            .and(headers("Accept", vec!["application/ld+json", "application/json;q=0.9", "*/*;q=0.1"]))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "text/html")
+                    .insert_header("Link", "</context.jsonld>; rel=\"alternate\"; type=\"application/ld+json\"")
+                    .set_body_string("<html></html>"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/context.jsonld"))
+            .and(headers("Accept", vec!["application/ld+json", "application/json;q=0.9", "*/*;q=0.1"]))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "application/ld+json")
+                    .set_body_json(json!({ "@context": { "@vocab": SCHEMA_IRI, "d": DBP_IRI } })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let resolver = RefResolver::new(None, None, false);
+        let context = format!("{}/context", server.uri());
+        // The second document reuses the fetched context.
+        for _ in 0..2 {
+            let document = json!({ "@context": context, "name": "n", "d:pattern": "p" });
+            assert_eq!(
+                normalized(document, &resolver).await,
+                json!({ "@context": canonical_context(), "schema:name": "n", "dbp:pattern": "p" })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_documents_without_a_context_fall_back() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "name": "not a context" })))
+            .mount(&server)
+            .await;
+
+        let document = json!({ "@context": server.uri(), "schema:name": "n" });
+        assert_eq!(
+            normalized(document, &RefResolver::new(None, None, false)).await,
+            json!({ "@context": canonical_context(), "schema:name": "n" })
+        );
+    }
+}
@@ -1,81 +1,194 @@
+use std::sync::{Arc, Mutex};
+
 use async_recursion::async_recursion;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use futures::future::join_all;
 use serde_json::Value;
 
+use crate::ref_resolver::RefResolver;
+use crate::time_parser;
+
 use dbp_schema::dbp_schema::{RealWorldDataset, RealWorldDataStructureInfo, RealWorldDataStructureItem, RealWorldDataStoringInfo, RealWorldDataCollectionInfo, EntryPoint};
 
-fn string_getter(val: &Value, key: &str) -> Option<String> {
-    if let Some(v) = val.get(key) { Some(String::from(v.as_str().unwrap())) } else { None }
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ParseMode {
+    /// Keep going on type mismatches, logging them as warnings
+    #[default]
+    Lenient,
+    /// Fail with every type mismatch found
+    Strict,
+}
+
+/// Default nesting limit for inlining `@ref` documents.
+pub const DEFAULT_MAX_DEPTH: usize = 6;
+/// Default nesting limit for `dbp:generatedFrom` below a dataset.
+pub const DEFAULT_PROVENANCE_DEPTH: usize = 6;
+
+/// A problem found while parsing, located by its JSON path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub message: String,
+}
+
+/// Collects diagnostics while parsing. Getters never panic on unexpected
+/// JSON; they record what they found and return `None`, and the caller
+/// decides through the mode whether that is fatal.
+pub struct ParseContext {
+    pub resolver: Arc<RefResolver>,
+    /// Maximum nesting of `dbp:generatedFrom` below the top-level dataset.
+    pub max_depth: usize,
+    /// Zone of `schema:dateCreated` and other times without an offset.
+    pub time_zone: Tz,
+    diagnostics: Mutex<Vec<Diagnostic>>,
+    errors: Mutex<Vec<Diagnostic>>,
+}
+
+impl ParseContext {
+    pub fn new(resolver: Arc<RefResolver>, max_depth: usize, time_zone: Tz) -> Self {
+        ParseContext {
+            resolver,
+            max_depth,
+            time_zone,
+            diagnostics: Mutex::new(Vec::new()),
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn report(&self, path: &str, message: String) {
+        debug!("{}: {}", path, message);
+        self.diagnostics.lock().unwrap().push(Diagnostic { path: path.to_string(), message });
+    }
+
+    /// Records a structural problem (cycles, excessive depth) that is fatal
+    /// in every mode.
+    fn fail(&self, path: &str, message: String) {
+        debug!("{}: {}", path, message);
+        self.errors.lock().unwrap().push(Diagnostic { path: path.to_string(), message });
+    }
+
+    /// Drains the diagnostics collected so far.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.lock().unwrap())
+    }
+
+    /// Drains the fatal errors collected so far.
+    pub fn take_errors(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.errors.lock().unwrap())
+    }
 }
 
-fn i64_getter(val: &Value, key: &str) -> Option<i64> {
-    if let Some(v) = val.get(key) { v.as_i64() } else { None }
+pub fn child_path(path: &str, key: &str) -> String {
+    format!("{}.{}", path, key)
 }
 
-fn time_getter(val: &Value, key: &str) -> Option<prost_types::Timestamp> {
-    if let Some(v) = val.get(key) { 
-        Some(prost_types::Timestamp { seconds: i64_getter(v, "seconds").unwrap_or(0), nanos: i64_getter(v, "nanos").unwrap_or(0) as i32 })
-    } else { None }
+fn json_type(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
 }
 
-fn id_getter(val: &Value) -> Option<String> {
-    string_getter(val, "@id")
+fn string_getter(ctx: &ParseContext, val: &Value, key: &str, path: &str) -> Option<String> {
+    match val.get(key) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(v) => {
+            ctx.report(&child_path(path, key), format!("expected a string, found {}", json_type(v)));
+            None
+        }
+    }
+}
+
+fn time_getter(ctx: &ParseContext, val: &Value, key: &str, path: &str) -> Option<prost_types::Timestamp> {
+    match val.get(key) {
+        None | Some(Value::Null) => None,
+        Some(v) => match time_parser::parse_time(v, ctx.time_zone) {
+            Ok(dt) => Some(time_parser::to_timestamp(&dt)),
+            Err(e) => {
+                ctx.report(&child_path(path, key), e);
+                None
+            }
+        },
+    }
 }
 
-fn name_getter(val: &Value) -> Option<String> {
-    string_getter(val, "schema:name")
+fn id_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<String> {
+    string_getter(ctx, val, "@id", path)
 }
 
-fn url_getter(val: &Value) -> Option<String> {
-    string_getter(val, "schema:url")
+fn name_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<String> {
+    string_getter(ctx, val, "schema:name", path)
 }
 
-fn ref_getter(val: &Value) -> Option<String> {
-    string_getter(val, "@ref")
+fn url_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<String> {
+    string_getter(ctx, val, "schema:url", path)
 }
 
-async fn structure_info_getter(val: &Value) -> Option<RealWorldDataStructureInfo> {
-    if let Some(si_json) = val.get("dbp:structureInfo") {
-        if let Some(ref_url) = ref_getter(si_json) {
-            info!("ref_url: {}", ref_url);
-            let client = reqwest::Client::new();
-            let resp = match client.get(ref_url).send().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            let body = match resp.text().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            match serde_json::from_str::<Value>(body.as_str()) {
-                Ok(si_json) => Some(structure_info_parser(&si_json)),
-                Err(_) => None,
+fn ref_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<String> {
+    string_getter(ctx, val, "@ref", path)
+}
+
+/// Returns the object at `key`, fetched through its `@ref` when it only
+/// points at the document.
+async fn referenced_object_getter(ctx: &ParseContext, val: &Value, key: &str, path: &str) -> Option<Value> {
+    let obj = val.get(key)?;
+    if obj.is_null() {
+        return None;
+    }
+    let path = child_path(path, key);
+    if !obj.is_object() {
+        ctx.report(&path, format!("expected an object, found {}", json_type(obj)));
+        return None;
+    }
+    if let Some(ref_url) = ref_getter(ctx, obj, &path) {
+        match ctx.resolver.fetch(&ref_url).await {
+            Ok(fetched) => Some(fetched),
+            Err(e) => {
+                ctx.report(&path, format!("@ref could not be resolved: {}", e));
+                None
             }
-        } else {
-            Some(structure_info_parser(si_json))
         }
-    } else { None }
+    } else {
+        Some(obj.clone())
+    }
+}
+
+async fn structure_info_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<RealWorldDataStructureInfo> {
+    referenced_object_getter(ctx, val, "dbp:structureInfo", path)
+        .await
+        .map(|si_json| structure_info_parser(ctx, &si_json, &child_path(path, "dbp:structureInfo")))
 }
 
-fn structure_info_parser(si_json: &Value) -> RealWorldDataStructureInfo {
+fn structure_info_parser(ctx: &ParseContext, si_json: &Value, path: &str) -> RealWorldDataStructureInfo {
     RealWorldDataStructureInfo {
-        id: id_getter(si_json),
-        name: name_getter(si_json),
-        url: url_getter(si_json),
-        encoding_format: string_getter(si_json, "schema:encodingFormat"),
+        id: id_getter(ctx, si_json, path),
+        name: name_getter(ctx, si_json, path),
+        url: url_getter(ctx, si_json, path),
+        encoding_format: string_getter(ctx, si_json, "schema:encodingFormat", path),
         structure_items: if let Some(si_sis_json) = si_json.get("dbp:structureItems") {
             let mut si_sis = Vec::new();
+            let sis_path = child_path(path, "dbp:structureItems");
             if let Some(si_sis_json) = si_sis_json.as_array() {
-                for si_si_json in si_sis_json {
+                for (i, si_si_json) in si_sis_json.iter().enumerate() {
+                    let si_path = format!("{}[{}]", sis_path, i);
                     let si_si = RealWorldDataStructureItem {
-                        id: id_getter(si_si_json),
-                        name: name_getter(si_si_json),
-                        url: url_getter(si_si_json),
-                        structure_path: string_getter(si_si_json, "dbp:structurePath"),
-                        item_type: string_getter(si_si_json, "dbp:itemType"),
-                        item_vocab: string_getter(si_si_json, "dbp:itemVocab"),
+                        id: id_getter(ctx, si_si_json, &si_path),
+                        name: name_getter(ctx, si_si_json, &si_path),
+                        url: url_getter(ctx, si_si_json, &si_path),
+                        structure_path: string_getter(ctx, si_si_json, "dbp:structurePath", &si_path),
+                        item_type: string_getter(ctx, si_si_json, "dbp:itemType", &si_path),
+                        item_vocab: string_getter(ctx, si_si_json, "dbp:itemVocab", &si_path),
                     };
                     si_sis.push(si_si);
                 }
+            } else {
+                ctx.report(&sis_path, format!("expected an array, found {}", json_type(si_sis_json)));
             }
             si_sis
         } else { Vec::new() },
@@ -83,150 +196,217 @@ fn structure_info_parser(si_json: &Value) -> RealWorldDataStructureInfo {
 }
 
 
-async fn distribution_getter(val: &Value) -> Option<RealWorldDataStoringInfo> {
-    if let Some(d_json) = val.get("dbp:distribution") {
-        if let Some(ref_url) = ref_getter(d_json) {
-            info!("ref_url: {}", ref_url);
-            let client = reqwest::Client::new();
-            let resp = match client.get(ref_url).send().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            let body = match resp.text().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            match serde_json::from_str::<Value>(body.as_str()) {
-                Ok(d_json) => Some(distribution_parser(&d_json)),
-                Err(_) => None,
-            }
-        } else {
-            Some(distribution_parser(d_json))
-        }
-    } else { None }
+async fn distribution_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<RealWorldDataStoringInfo> {
+    referenced_object_getter(ctx, val, "dbp:distribution", path)
+        .await
+        .map(|d_json| distribution_parser(ctx, &d_json, &child_path(path, "dbp:distribution")))
 }
 
-fn distribution_parser(d_json: &Value) -> RealWorldDataStoringInfo {
+/// Parses a storing info (a `dbp:distribution`, a `schema:distribution` entry
+/// or an output store) whose `@ref`, if any, the caller already resolved.
+pub fn distribution_parser(ctx: &ParseContext, d_json: &Value, path: &str) -> RealWorldDataStoringInfo {
     RealWorldDataStoringInfo {
-        id: id_getter(d_json),
-        name: name_getter(d_json),
-        url: url_getter(d_json),
-        start_time: time_getter(d_json, "dbp:startTime"),
-        end_time: time_getter(d_json, "dbp:endTime"),
-        base_url: string_getter(d_json, "dbp:baseUrl"),
-        pattern: string_getter(d_json, "dbp:pattern"),
-    }
-}
-
-
-async fn collection_info_getter(val: &Value) -> Option<RealWorldDataCollectionInfo> {
-    if let Some(ci_json) = val.get("dbp:collectionInfo") {
-        if let Some(ref_url) = ref_getter(ci_json) {
-            info!("ref_url: {}", ref_url);
-            let client = reqwest::Client::new();
-            let resp = match client.get(ref_url).send().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            let body = match resp.text().await {
-                Ok(v) => v,
-                Err(_) => { return None },
-            };
-            match serde_json::from_str::<Value>(body.as_str()) {
-                Ok(ci_json) => Some(collection_info_parser(&ci_json)),
-                Err(_) => None,
-            }
-        } else {
-            Some(collection_info_parser(ci_json))
-        }
-    } else { None }
+        id: id_getter(ctx, d_json, path),
+        name: name_getter(ctx, d_json, path),
+        url: url_getter(ctx, d_json, path),
+        start_time: time_getter(ctx, d_json, "dbp:startTime", path),
+        end_time: time_getter(ctx, d_json, "dbp:endTime", path),
+        base_url: string_getter(ctx, d_json, "dbp:baseUrl", path),
+        pattern: string_getter(ctx, d_json, "dbp:pattern", path),
+    }
 }
 
-fn collection_info_parser(ci_json: &Value) -> RealWorldDataCollectionInfo {
+
+async fn collection_info_getter(ctx: &ParseContext, val: &Value, path: &str) -> Option<RealWorldDataCollectionInfo> {
+    referenced_object_getter(ctx, val, "dbp:collectionInfo", path)
+        .await
+        .map(|ci_json| collection_info_parser(ctx, &ci_json, &child_path(path, "dbp:collectionInfo")))
+}
+
+fn collection_info_parser(ctx: &ParseContext, ci_json: &Value, path: &str) -> RealWorldDataCollectionInfo {
     RealWorldDataCollectionInfo {
-        id: id_getter(ci_json),
-        name: name_getter(ci_json),
-        url: url_getter(ci_json),
-        collection_style: string_getter(ci_json, "dbp:collectionStyle"),
-        collection_protocol: string_getter(ci_json, "dbp:collectionProtocol"),
-        listen_address: string_getter(ci_json, "dbp:listenAddress"),
-        server_address: string_getter(ci_json, "dbp:serverAddress"),
+        id: id_getter(ctx, ci_json, path),
+        name: name_getter(ctx, ci_json, path),
+        url: url_getter(ctx, ci_json, path),
+        collection_style: string_getter(ctx, ci_json, "dbp:collectionStyle", path),
+        collection_protocol: string_getter(ctx, ci_json, "dbp:collectionProtocol", path),
+        listen_address: string_getter(ctx, ci_json, "dbp:listenAddress", path),
+        server_address: string_getter(ctx, ci_json, "dbp:serverAddress", path),
         entry_point: if let Some(ep) = ci_json.get("dbp:entryPoint") {
+            let path = &child_path(path, "dbp:entryPoint");
             Some(
                 EntryPoint {
-                    action_application: string_getter(ep, "schema:actionApplication"),
-                    action_platform: string_getter(ep, "schema:actionPlatform"),
-                    content_type: string_getter(ep, "schema:contentType"),
-                    encoding_type: string_getter(ep, "schema:encodingType"),
-                    http_method: string_getter(ep, "schema:httpMethod"),
-                    url_template: string_getter(ep, "schema:urlTemplate"),
-                    additional_type: string_getter(ep, "schema:additionalType"),
-                    alternate_name: string_getter(ep, "schema:alternateName"),
-                    description: string_getter(ep, "schema:description"),
-                    disambiguating_description: string_getter(ep, "schema:disambiguatingDescription"),
-                    id: id_getter(ep),
-                    image: string_getter(ep, "schema:image"),
-                    main_entity_of_page: string_getter(ep, "schema:mainEntityOfPage"),
-                    name: name_getter(ep),
-                    potential_action: string_getter(ep, "schema:potentialAction"),
-                    same_as: string_getter(ep, "schema:sameAs"),
-                    subject_of: string_getter(ep, "schema:subjectOf"),
-                    url: url_getter(ep),
+                    action_application: string_getter(ctx, ep, "schema:actionApplication", path),
+                    action_platform: string_getter(ctx, ep, "schema:actionPlatform", path),
+                    content_type: string_getter(ctx, ep, "schema:contentType", path),
+                    encoding_type: string_getter(ctx, ep, "schema:encodingType", path),
+                    http_method: string_getter(ctx, ep, "schema:httpMethod", path),
+                    url_template: string_getter(ctx, ep, "schema:urlTemplate", path),
+                    additional_type: string_getter(ctx, ep, "schema:additionalType", path),
+                    alternate_name: string_getter(ctx, ep, "schema:alternateName", path),
+                    description: string_getter(ctx, ep, "schema:description", path),
+                    disambiguating_description: string_getter(ctx, ep, "schema:disambiguatingDescription", path),
+                    id: id_getter(ctx, ep, path),
+                    image: string_getter(ctx, ep, "schema:image", path),
+                    main_entity_of_page: string_getter(ctx, ep, "schema:mainEntityOfPage", path),
+                    name: name_getter(ctx, ep, path),
+                    potential_action: string_getter(ctx, ep, "schema:potentialAction", path),
+                    same_as: string_getter(ctx, ep, "schema:sameAs", path),
+                    subject_of: string_getter(ctx, ep, "schema:subjectOf", path),
+                    url: url_getter(ctx, ep, path),
                 }
             )
         } else { None },
     }
 }
 
+/// Parses a dataset and, recursively, everything it was generated from.
+/// Independent `@ref`s (structure, collection and distribution info, and
+/// every `generatedFrom` entry) are resolved concurrently. `ancestors` holds
+/// the `@id`s (or `@ref` URLs) of the datasets above this one, so that cycles
+/// and graphs deeper than `ctx.max_depth` are reported instead of followed.
 #[async_recursion]
-async fn dataset_parser(rwd_json: &Value) -> RealWorldDataset {
+async fn dataset_parser(ctx: &ParseContext, rwd_json: &Value, path: &str, ancestors: &[String]) -> RealWorldDataset {
+    let ref_url = ref_getter(ctx, rwd_json, path);
+    let resolved;
+    let rwd_json = match &ref_url {
+        Some(ref_url) => match ctx.resolver.fetch(ref_url).await {
+            Ok(fetched) => {
+                resolved = fetched;
+                &resolved
+            }
+            Err(e) => {
+                ctx.report(path, format!("@ref could not be resolved: {}", e));
+                return RealWorldDataset::default();
+            }
+        },
+        None => rwd_json,
+    };
     debug!("RWD: {:?}", rwd_json);
+
+    let identity = id_getter(ctx, rwd_json, path).or(ref_url);
+    if let Some(identity) = &identity {
+        if ancestors.contains(identity) {
+            let cycle: Vec<&str> = ancestors
+                .iter()
+                .skip_while(|a| *a != identity)
+                .map(|a| a.as_str())
+                .chain(std::iter::once(identity.as_str()))
+                .collect();
+            ctx.fail(path, format!("cycle in dbp:generatedFrom: {}", cycle.join(" -> ")));
+            return RealWorldDataset { id: Some(identity.clone()), ..Default::default() };
+        }
+    }
+    if ancestors.len() > ctx.max_depth {
+        ctx.fail(path, format!(
+            "dbp:generatedFrom is nested deeper than the maximum depth of {} ({})",
+            ctx.max_depth,
+            ancestors.join(" -> ")
+        ));
+        return RealWorldDataset { id: identity, ..Default::default() };
+    }
+    let mut lineage = ancestors.to_vec();
+    lineage.push(identity.clone().unwrap_or_else(|| path.to_string()));
+
+    let gfs_path = child_path(path, "dbp:generatedFrom");
+    let gfs_json: Vec<&Value> = match rwd_json.get("dbp:generatedFrom") {
+        Some(Value::Array(gfs_json)) => gfs_json.iter().collect(),
+        Some(gfs_json) => {
+            ctx.report(&gfs_path, format!("expected an array, found {}", json_type(gfs_json)));
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    let gf_paths: Vec<String> = (0..gfs_json.len()).map(|i| format!("{}[{}]", gfs_path, i)).collect();
+
+    let (structure_info, collection_info, distribution, generated_from) = tokio::join!(
+        structure_info_getter(ctx, rwd_json, path),
+        collection_info_getter(ctx, rwd_json, path),
+        distribution_getter(ctx, rwd_json, path),
+        join_all(gfs_json.iter().zip(&gf_paths).map(|(gf_json, gf_path)| dataset_parser(ctx, gf_json, gf_path, &lineage))),
+    );
+
     RealWorldDataset {
-        id: id_getter(rwd_json),
-        name: name_getter(rwd_json),
-        url: url_getter(rwd_json),
-        structure_info: structure_info_getter(rwd_json).await,
-        generated_from: if let Some(gfs_json) = rwd_json.get("dbp:generatedFrom") {
-            let mut gf = Vec::new();
-            if let Some(gfs_json) = gfs_json.as_array() {
-                for gf_json in gfs_json {
-                    gf.push(dataset_parser(gf_json).await);
-                }
-            }
-            gf
-        } else { Vec::new() },
+        id: identity,
+        name: name_getter(ctx, rwd_json, path),
+        url: url_getter(ctx, rwd_json, path),
+        structure_info,
+        generated_from,
         generated_using: None,
         generated_args: Vec::new(),
-        collection_info: collection_info_getter(rwd_json).await,
-        distribution: distribution_getter(rwd_json).await,
-        author: string_getter(rwd_json, "schema:author"),
-        content_location: string_getter(rwd_json, "schema:contentLocation"),
-        date_created: time_getter(rwd_json, "schema:dateCreated"),
-        date_modified: time_getter(rwd_json, "schema:dateModified"),
-        date_published: time_getter(rwd_json, "schema:datePublished"),
-        license: string_getter(rwd_json, "schema:license"),
-        location_created: string_getter(rwd_json, "schema:locationCreated"),
-        description: string_getter(rwd_json, "schema:description"),
-    }
-}
-
-pub async fn get_real_world_datasets(
-    url: &str,
-) -> Result<Vec<RealWorldDataset>, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let resp = client.get(url).send().await?;
-    let body = resp.text().await?;
-
-    let mut rwds: Vec<RealWorldDataset> = Vec::new();
-
-    if let Some(rwds_json) = serde_json::from_str::<Value>(body.as_str()).unwrap().as_array() {
-        for rwd_json in rwds_json {
-            debug!("RWD: {:?}", rwd_json);
-            let rwd = dataset_parser(rwd_json).await;
-            rwds.push(rwd);
-        }
+        collection_info,
+        distribution,
+        author: string_getter(ctx, rwd_json, "schema:author", path),
+        content_location: string_getter(ctx, rwd_json, "schema:contentLocation", path),
+        date_created: time_getter(ctx, rwd_json, "schema:dateCreated", path),
+        date_modified: time_getter(ctx, rwd_json, "schema:dateModified", path),
+        date_published: time_getter(ctx, rwd_json, "schema:datePublished", path),
+        license: string_getter(ctx, rwd_json, "schema:license", path),
+        location_created: string_getter(ctx, rwd_json, "schema:locationCreated", path),
+        description: string_getter(ctx, rwd_json, "schema:description", path),
     }
+}
 
+pub async fn dataset_getter(ctx: &ParseContext, rwd_json: &Value, path: &str) -> RealWorldDataset {
+    dataset_parser(ctx, rwd_json, path, &[]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context(max_depth: usize) -> ParseContext {
+        ParseContext::new(Arc::new(RefResolver::new(None, None, true)), max_depth, Tz::UTC)
+    }
 
-    Ok(rwds)
+    /// A chain of datasets `d0 <- d1 <- ...`, each generated from the next.
+    fn chain(len: usize) -> Value {
+        (0..len).rev().fold(Value::Null, |inner, i| match inner {
+            Value::Null => json!({ "@id": format!("d{}", i) }),
+            inner => json!({ "@id": format!("d{}", i), "dbp:generatedFrom": [inner] }),
+        })
+    }
+
+    #[tokio::test]
+    async fn fails_on_generated_from_cycles() {
+        let ctx = context(DEFAULT_PROVENANCE_DEPTH);
+        let dataset = json!({
+            "@id": "a",
+            "dbp:generatedFrom": [
+                { "@id": "b", "dbp:generatedFrom": [{ "@id": "c", "dbp:generatedFrom": [{ "@id": "b" }] }] },
+                { "@id": "d" },
+            ],
+        });
+        let parsed = dataset_getter(&ctx, &dataset, "$").await;
+        assert_eq!(parsed.generated_from[1].id.as_deref(), Some("d"));
+        assert_eq!(
+            ctx.take_errors(),
+            [Diagnostic {
+                path: "$.dbp:generatedFrom[0].dbp:generatedFrom[0].dbp:generatedFrom[0]".to_string(),
+                message: "cycle in dbp:generatedFrom: b -> c -> b".to_string(),
+            }]
+        );
+
+        // The same dataset twice side by side is not a cycle.
+        let dataset = json!({ "@id": "a", "dbp:generatedFrom": [{ "@id": "b" }, { "@id": "b" }] });
+        dataset_getter(&ctx, &dataset, "$").await;
+        assert!(ctx.take_errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fails_past_the_maximum_depth() {
+        let cases = [(0, 1, true), (0, 2, false), (2, 3, true), (2, 4, false)];
+        for (max_depth, len, ok) in cases {
+            let ctx = context(max_depth);
+            dataset_getter(&ctx, &chain(len), "$").await;
+            let errors = ctx.take_errors();
+            assert_eq!(errors.is_empty(), ok, "depth {} of {}: {:?}", len, max_depth, errors);
+            if !ok {
+                let path = format!("${}", ".dbp:generatedFrom[0]".repeat(max_depth + 1));
+                assert_eq!(errors[0].path, path);
+                assert!(errors[0].message.contains(&format!("maximum depth of {}", max_depth)), "{}", errors[0].message);
+            }
+        }
+    }
 }
@@ -0,0 +1,359 @@
+// Standard Library
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// External Library
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::brewing_demand::BrewingArgument;
+use crate::protocols;
+
+pub const MANIFEST_FILE_NAME: &str = "_lineage.json";
+pub const SIDECAR_SUFFIX: &str = ".lineage.json";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum LineageMode {
+    /// Do not record lineage
+    #[default]
+    Off,
+    /// Write `<output>.lineage.json` next to every brewed file
+    Sidecar,
+    /// Write a single `_lineage.json` manifest at the output base
+    Manifest,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+    pub mtime: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineageEntry {
+    /// Output path relative to the output base (or to the sidecar's directory).
+    pub output: String,
+    pub output_sha256: String,
+    pub output_size: u64,
+    pub inputs: Vec<FileDigest>,
+    pub brewer_name: String,
+    pub brewer_version: String,
+    pub argument_hash: String,
+    pub brewed_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LineageManifest {
+    pub output_base_url: String,
+    pub entries: Vec<LineageEntry>,
+}
+
+pub struct LineageRecorder {
+    mode: LineageMode,
+    argument_hash: String,
+    entries: Vec<LineageEntry>,
+}
+
+impl LineageRecorder {
+    /// Every brewing argument goes into the argument hash, in order, since
+    /// any of them can change what is brewed.
+    pub fn new(mode: LineageMode, brewing_arguments: &[BrewingArgument]) -> Self {
+        let pairs: Vec<(&str, &str)> = brewing_arguments.iter().map(|arg| (arg.key.as_str(), arg.value.as_str())).collect();
+        let arguments = serde_json::to_string(&pairs).unwrap_or_default();
+        LineageRecorder {
+            mode,
+            argument_hash: to_hex(&Sha256::digest(arguments.as_bytes())),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records one brewed slot. Sidecars are written immediately; manifest
+    /// entries are kept until `finish`.
+    pub fn record(
+        &mut self,
+        input_urls: &[String],
+        output_base_url: &str,
+        output_url: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.mode == LineageMode::Off {
+            return Ok(());
+        }
+        let inputs = input_urls
+            .iter()
+            .map(|url| digest_file(url))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_digest = digest_file(output_url)?;
+        let output_path = output_url.replace(protocols::FILE, "");
+
+        let relative_to = match self.mode {
+            LineageMode::Sidecar => Path::new(&output_path)
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default(),
+            _ => PathBuf::from(output_base_url.replace(protocols::FILE, "")),
+        };
+        let entry = LineageEntry {
+            output: Path::new(&output_path)
+                .strip_prefix(&relative_to)
+                .unwrap_or(Path::new(&output_path))
+                .to_string_lossy()
+                .to_string(),
+            output_sha256: output_digest.sha256,
+            output_size: output_digest.size,
+            inputs,
+            brewer_name: crate::BREWER_NAME.to_string(),
+            brewer_version: env!("CARGO_PKG_VERSION").to_string(),
+            argument_hash: self.argument_hash.clone(),
+            brewed_at: Local::now().to_rfc3339(),
+        };
+
+        if self.mode == LineageMode::Sidecar {
+            let sidecar_path = format!("{}{}", output_path, SIDECAR_SUFFIX);
+            fs::write(&sidecar_path, serde_json::to_string_pretty(&entry)?)?;
+            debug!("Wrote lineage sidecar {}", sidecar_path);
+        } else {
+            self.entries.retain(|e| e.output != entry.output);
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    /// Writes the manifest, merging with entries left by earlier runs over
+    /// the same output base.
+    pub fn finish(&self, output_base_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.mode != LineageMode::Manifest || !output_base_url.starts_with(protocols::FILE) {
+            return Ok(());
+        }
+        let manifest_path = Path::new(&output_base_url.replace(protocols::FILE, "")).join(MANIFEST_FILE_NAME);
+        let mut manifest = match fs::read_to_string(&manifest_path) {
+            Ok(text) => serde_json::from_str::<LineageManifest>(&text)?,
+            Err(_) => LineageManifest { output_base_url: output_base_url.to_string(), entries: Vec::new() },
+        };
+        for entry in &self.entries {
+            manifest.entries.retain(|e| e.output != entry.output);
+            manifest.entries.push(entry.clone());
+        }
+        manifest.entries.sort_by(|a, b| a.output.cmp(&b.output));
+        fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        info!("Wrote lineage manifest {} ({} entries)", manifest_path.display(), manifest.entries.len());
+        Ok(())
+    }
+}
+
+pub fn digest_file(url: &str) -> Result<FileDigest, Box<dyn std::error::Error>> {
+    let path = url.replace(protocols::FILE, "");
+    let mut file = fs::File::open(&path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    let mtime = file
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .map(|t| DateTime::<Local>::from(t).to_rfc3339());
+    Ok(FileDigest { url: url.to_string(), sha256: to_hex(&hasher.finalize()), size, mtime })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Rechecks a finished output tree against its manifest and any sidecars.
+/// Returns the number of entries checked, or an error listing every mismatch.
+pub fn verify(output_base_url: &str, check_inputs: bool) -> Result<usize, Box<dyn std::error::Error>> {
+    let base = PathBuf::from(output_base_url.replace(protocols::FILE, ""));
+    let mut checks: Vec<(PathBuf, LineageEntry)> = Vec::new();
+
+    let manifest_path = base.join(MANIFEST_FILE_NAME);
+    if manifest_path.exists() {
+        let manifest: LineageManifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)?;
+        checks.extend(manifest.entries.into_iter().map(|e| (base.join(&e.output), e)));
+    }
+    let mut sidecars = Vec::new();
+    collect_sidecars(&base, &mut sidecars)?;
+    for sidecar in sidecars {
+        let entry: LineageEntry = serde_json::from_str(&fs::read_to_string(&sidecar)?)?;
+        let dir = sidecar.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        checks.push((dir.join(&entry.output), entry));
+    }
+    if checks.is_empty() {
+        return Err(format!("No lineage manifest or sidecars found under {}", base.display()).into());
+    }
+
+    let mut failures = Vec::new();
+    for (path, entry) in &checks {
+        match digest_file(&path.to_string_lossy()) {
+            Ok(d) if d.sha256 == entry.output_sha256 && d.size == entry.output_size => {
+                debug!("OK {}", path.display());
+            }
+            Ok(d) => failures.push(format!(
+                "{}: expected sha256 {} ({} bytes), found {} ({} bytes)",
+                path.display(), entry.output_sha256, entry.output_size, d.sha256, d.size
+            )),
+            Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+        }
+        if check_inputs {
+            for input in &entry.inputs {
+                match digest_file(&input.url) {
+                    Ok(d) if d.sha256 == input.sha256 && d.size == input.size => {
+                        if !same_mtime(&input.mtime, &d.mtime) {
+                            warn!(
+                                "{} (input of {}): modification time changed from {} to {}, but its content is unchanged",
+                                input.url, entry.output, input.mtime.as_deref().unwrap_or("?"), d.mtime.as_deref().unwrap_or("?")
+                            );
+                        }
+                    }
+                    Ok(d) => failures.push(format!(
+                        "{} (input of {}): expected sha256 {} (modified {}), found {} (modified {})",
+                        input.url, entry.output, input.sha256, input.mtime.as_deref().unwrap_or("?"),
+                        d.sha256, d.mtime.as_deref().unwrap_or("?")
+                    )),
+                    Err(e) => failures.push(format!("{} (input of {}): {}", input.url, entry.output, e)),
+                }
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(checks.len())
+    } else {
+        Err(format!(
+            "{} lineage check(s) failed across {} entries:\n{}",
+            failures.len(), checks.len(), failures.join("\n")
+        ).into())
+    }
+}
+
+/// Modification times are compared as instants, since they are recorded in
+/// the local offset of whichever machine brewed the output. A missing time
+/// on either side is not a mismatch.
+fn same_mtime(recorded: &Option<String>, found: &Option<String>) -> bool {
+    match (recorded, found) {
+        (Some(recorded), Some(found)) => {
+            match (DateTime::parse_from_rfc3339(recorded), DateTime::parse_from_rfc3339(found)) {
+                (Ok(recorded), Ok(found)) => recorded == found,
+                _ => recorded == found,
+            }
+        }
+        _ => true,
+    }
+}
+
+fn collect_sidecars(dir: &Path, sidecars: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sidecars(&path, sidecars)?;
+        } else if path.to_string_lossy().ends_with(SIDECAR_SUFFIX) {
+            sidecars.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dbp-lineage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn url(path: &Path) -> String {
+        format!("{}{}", protocols::FILE, path.display())
+    }
+
+    fn argument(key: &str, value: &str) -> BrewingArgument {
+        BrewingArgument { key: key.to_string(), value: value.to_string() }
+    }
+
+    #[test]
+    fn digests_content_and_size() {
+        let dir = scratch("digest");
+        let path = dir.join("a.csv");
+        fs::write(&path, "abc").unwrap();
+
+        let digest = digest_file(&url(&path)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(digest.sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(digest.size, 3);
+        assert!(digest.mtime.is_some());
+        assert!(digest_file(&url(&path)).is_err());
+    }
+
+    #[test]
+    fn every_argument_changes_the_hash() {
+        let hash = |arguments: &[BrewingArgument]| LineageRecorder::new(LineageMode::Manifest, arguments).argument_hash;
+        let base = hash(&[argument("sample_key", "a"), argument("csv_columns", "x,y")]);
+        assert_eq!(base, hash(&[argument("sample_key", "a"), argument("csv_columns", "x,y")]));
+        for changed in [
+            vec![argument("sample_key", "a"), argument("csv_columns", "x")],
+            vec![argument("sample_key", "a"), argument("csv_columns", "x,y"), argument("filter", "x > 1")],
+            vec![argument("csv_columns", "x,y"), argument("sample_key", "a")],
+        ] {
+            assert_ne!(base, hash(&changed), "{:?}", changed);
+        }
+    }
+
+    #[test]
+    fn verify_reports_changed_outputs_and_inputs() {
+        let dir = scratch("verify");
+        let (input, output) = (dir.join("in.csv"), dir.join("out/2023.csv"));
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::write(&input, "a\n1\n").unwrap();
+        fs::write(&output, "a\n1\n").unwrap();
+        let base = url(&dir.join("out"));
+        let mut recorder = LineageRecorder::new(LineageMode::Manifest, &[]);
+        recorder.record(&[url(&input)], &base, &url(&output)).unwrap();
+        recorder.finish(&base).unwrap();
+
+        let checked = verify(&base, true);
+        fs::write(&input, "a\n2\n").unwrap();
+        let input_changed = verify(&base, true).unwrap_err().to_string();
+        let inputs_skipped = verify(&base, false);
+        fs::write(&output, "a\n2\n").unwrap();
+        let output_changed = verify(&base, false).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(checked.unwrap(), 1);
+        assert!(input_changed.contains("(input of 2023.csv): expected sha256"), "{}", input_changed);
+        assert_eq!(inputs_skipped.unwrap(), 1);
+        assert!(output_changed.starts_with("1 lineage check(s) failed across 1 entries:"), "{}", output_changed);
+        assert!(output_changed.contains("2023.csv: expected sha256"), "{}", output_changed);
+    }
+
+    #[test]
+    fn mtimes_compare_as_instants() {
+        let some = |text: &str| Some(text.to_string());
+        let cases = [
+            (some("2023-09-01T09:00:00+09:00"), some("2023-09-01T00:00:00+00:00"), true),
+            (some("2023-09-01T09:00:00+09:00"), some("2023-09-01T09:00:01+09:00"), false),
+            (some("yesterday"), some("yesterday"), true),
+            (some("yesterday"), some("today"), false),
+            (None, some("2023-09-01T00:00:00Z"), true),
+            (some("2023-09-01T00:00:00Z"), None, true),
+        ];
+        for (recorded, found, expected) in cases {
+            assert_eq!(same_mtime(&recorded, &found), expected, "{:?} vs {:?}", recorded, found);
+        }
+    }
+}
@@ -3,27 +3,103 @@ extern crate log;
 extern crate env_logger as logger;
 
 // Standard Library
+use std::collections::BTreeSet;
 use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
 
 // Ecternal Library
-use chrono::{DateTime, Duration, Local};
-use clap::Parser;
-use serde_json::{Map, Value}; 
+use chrono::{DateTime, Local, Offset};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
+use serde_json::{Map, Value};
 
 use json_ld_utils::{
-    load_json_ld, scan_json_ld_obj, DBP_BASE_URL, DBP_BREWER_INFO, DBP_BREWER_OUTPUT_STORE,
-    DBP_PATTERN, DBP_RWD_BREWING_DEMAND, DBP_TIME_PERIOD_END, DBP_TIME_PERIOD_START, SC_NAME
+    DBP_BREWER_INFO, DBP_RWD_BREWING_DEMAND, DBP_TIME_PERIOD_END,
+    DBP_TIME_PERIOD_START, SC_NAME
 };
-use utils::extract_data_sets;
+use brewing_demand::BrewingDemand;
+use catalog::Catalog;
+use completeness::{CompletenessFormat, CompletenessReport};
+use compression::Compression;
+use input_matcher::MatchMode;
+use inventory::Inventory;
+use json_ld_loader::{ParseMode, DEFAULT_MAX_DEPTH, DEFAULT_PROVENANCE_DEPTH};
+use ref_resolver::RefResolver;
+use lineage::{LineageMode, LineageRecorder};
+use placeholders::Values;
+use plan::{BrewPlan, DistributionPlan, OutOfWindowPolicy};
+use slots::{Alignment, IntervalMode};
+use status_reporter::{DemandState, Progress, ReportOptions, StatusReporter};
+use text_encoding::TextEncoding;
 
+mod brewer;
+mod brewing_demand;
+mod catalog;
+mod completeness;
+mod compression;
+mod filter_expr;
+mod input_matcher;
+mod inventory;
+mod json_brewer;
+mod json_ld_context;
+mod json_ld_loader;
+mod lineage;
+mod placeholders;
+mod plan;
 mod protocols;
+mod provenance;
+mod slots;
+mod ref_resolver;
+mod status_reporter;
+mod text_encoding;
+mod time_parser;
 mod utils;
+mod worker;
 
+mod csv_brewer;
 mod data_brewer_micro;
 
+const BREWER_NAME: &str = "dbpBrewerTemplate";
+
+#[derive(Clone, Debug, Default)]
+pub struct DemandOptions {
+    /// Demands whose `dbp:brewerInfo` names another brewer are refused.
+    pub brewer_name: String,
+    pub report: ReportOptions,
+    pub publish_dataset_url: Option<String>,
+    pub lineage: LineageMode,
+    pub parse_mode: ParseMode,
+    pub ref_cache_dir: Option<PathBuf>,
+    pub max_depth: usize,
+    pub provenance_depth: usize,
+    pub catalog: Option<PathBuf>,
+    pub offline: bool,
+    pub out_of_window: OutOfWindowPolicy,
+    pub accept_formats: Vec<String>,
+    pub timezone: Tz,
+    pub interval: IntervalMode,
+    pub alignment: Alignment,
+    pub match_mode: MatchMode,
+    pub completeness: CompletenessFormat,
+    pub output_compression: Option<Compression>,
+    pub encoding: TextEncoding,
+    pub dry_run: bool,
+}
+
+/// What a successful brew wrote, used to describe the output store afterwards.
+struct BrewedOutput {
+    base_url: String,
+    pattern: String,
+    range: Option<(DateTime<Tz>, DateTime<Tz>)>,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(
         short = 'j',
         long = "json_ld",
@@ -35,219 +111,653 @@ struct Args {
         short = 'l',
         long = "log_level",
         value_name = "Log Level (ERROR, INFO, DEBUG)",
-        default_value = "INFO"
+        default_value = "INFO",
+        global = true
     )]
     log_level: String,
+    /// PATCH demand state transitions (accepted, running, succeeded, failed) back to the demand URL
+    #[arg(long = "report_status", global = true)]
+    report_status: bool,
+    #[arg(
+        long = "status_callback_url",
+        value_name = "URL to POST demand state transitions to instead of the demand URL",
+        global = true
+    )]
+    status_callback_url: Option<String>,
+    #[arg(
+        long = "publish_dataset_url",
+        value_name = "RWDB URL to POST the brewed RealWorldDataset JSON-LD to",
+        global = true
+    )]
+    publish_dataset_url: Option<String>,
+    #[arg(
+        long = "lineage",
+        value_enum,
+        value_name = "Lineage recording for brewed files",
+        default_value_t = LineageMode::Off,
+        global = true
+    )]
+    lineage: LineageMode,
+    #[arg(
+        long = "parse_mode",
+        value_enum,
+        value_name = "How to treat JSON-LD type mismatches",
+        default_value_t = ParseMode::Lenient,
+        global = true
+    )]
+    parse_mode: ParseMode,
+    #[arg(
+        long = "ref_cache_dir",
+        value_name = "Directory caching fetched @ref documents between runs",
+        global = true
+    )]
+    ref_cache_dir: Option<PathBuf>,
+    #[arg(
+        long = "max_depth",
+        value_name = "Maximum nesting depth of inlined JSON-LD @ref documents",
+        default_value_t = DEFAULT_MAX_DEPTH,
+        global = true
+    )]
+    max_depth: usize,
+    #[arg(
+        long = "provenance_depth",
+        value_name = "Maximum dbp:generatedFrom nesting depth below an input dataset",
+        default_value_t = DEFAULT_PROVENANCE_DEPTH,
+        global = true
+    )]
+    provenance_depth: usize,
+    #[arg(
+        long = "catalog",
+        value_name = "Catalog directory or bundle file resolving @ref and @context URLs locally",
+        global = true
+    )]
+    catalog: Option<PathBuf>,
+    /// Never fetch over the network; resolve everything from the catalog and ref cache
+    #[arg(long = "offline", global = true)]
+    offline: bool,
+    #[arg(
+        long = "out_of_window",
+        value_enum,
+        value_name = "What to do with slots outside a distribution's declared window",
+        default_value_t = OutOfWindowPolicy::Skip,
+        global = true
+    )]
+    out_of_window: OutOfWindowPolicy,
+    #[arg(
+        long = "accept_format",
+        value_name = "Accepted schema:encodingFormat values in order of preference (comma separated)",
+        value_delimiter = ',',
+        global = true
+    )]
+    accept_format: Vec<String>,
+    #[arg(
+        long = "timezone",
+        value_name = "IANA time zone for expanding patterns and stepping slots when the demand has no dbp:timeZone",
+        default_value = "UTC",
+        global = true
+    )]
+    timezone: Tz,
+    #[arg(
+        long = "interval",
+        value_enum,
+        value_name = "Whether dbp:timePeriodEnd is inclusive (closed) or exclusive (half_open)",
+        default_value_t = IntervalMode::Closed,
+        global = true
+    )]
+    interval: IntervalMode,
+    #[arg(
+        long = "align",
+        value_enum,
+        value_name = "Alignment of dbp:timePeriodStart to the output pattern's slot boundaries",
+        default_value_t = Alignment::None,
+        global = true
+    )]
+    align: Alignment,
+    #[arg(
+        long = "match_mode",
+        value_enum,
+        value_name = "Brew each file a wildcard pattern matches separately (each) or concatenated (group)",
+        default_value_t = MatchMode::Each,
+        global = true
+    )]
+    match_mode: MatchMode,
+    #[arg(
+        long = "completeness",
+        value_enum,
+        value_name = "Print expected, present, missing and empty slots of the inputs and output before and after brewing",
+        default_value_t = CompletenessFormat::Off,
+        global = true
+    )]
+    completeness: CompletenessFormat,
+    #[arg(
+        long = "output_compression",
+        value_enum,
+        value_name = "Compression of brewed files (default: from the output pattern's extension)",
+        global = true
+    )]
+    output_compression: Option<Compression>,
+    #[arg(
+        long = "input_encoding",
+        value_name = "Character encoding of every input, overriding the charset of schema:encodingFormat and detection",
+        global = true
+    )]
+    input_encoding: Option<String>,
+    #[arg(
+        long = "output_encoding",
+        value_name = "Character encoding of brewed files (e.g. UTF-8, Shift_JIS, EUC-JP, UTF-16LE)",
+        default_value = "UTF-8",
+        global = true
+    )]
+    output_encoding: String,
+    /// Start UTF-8 and UTF-16 outputs with a byte order mark
+    #[arg(long = "output_bom", global = true)]
+    output_bom: bool,
+    /// Validate the demand and print the brewing plan without reading or writing data
+    #[arg(long = "dry_run", global = true)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Poll the demand list API and brew every pending demand addressed to this brewer
+    Worker {
+        #[arg(
+            long = "demand_list_url",
+            value_name = "RealWorldDataBrewingDemand List API URL",
+            default_value = "https://dev-rwdb.srv.exdata.co.jp/api/v0/brewing_demands/?format=json"
+        )]
+        demand_list_url: String,
+        #[arg(
+            long = "brewer_name",
+            value_name = "Brewer name matched against dbp:brewerInfo schema:name",
+            default_value = BREWER_NAME
+        )]
+        brewer_name: String,
+        #[arg(
+            long = "poll_interval",
+            value_name = "Seconds between polls",
+            default_value_t = 60
+        )]
+        poll_interval: u64,
+        #[arg(
+            long = "max_backoff",
+            value_name = "Maximum seconds to wait after failed polls",
+            default_value_t = 900
+        )]
+        max_backoff: u64,
+        /// Poll once and exit instead of looping forever
+        #[arg(long = "once")]
+        once: bool,
+    },
+    /// Recheck a finished output tree against its lineage manifest or sidecars
+    Verify {
+        #[arg(
+            long = "output",
+            value_name = "Output store base URL (file://...)"
+        )]
+        output: String,
+        /// Also recheck the recorded input files
+        #[arg(long = "check_inputs")]
+        check_inputs: bool,
+    },
+    /// List an input tree and report the time slots present under a pattern, with the gaps
+    Discover {
+        #[arg(
+            long = "base_url",
+            value_name = "Input store base URL (file://...)"
+        )]
+        base_url: String,
+        #[arg(
+            long = "pattern",
+            value_name = "dbp:pattern of the input store (e.g. %Y/%Y-%m-%d.extention)"
+        )]
+        pattern: String,
+    },
+    /// Crawl a demand online and save every referenced document into a catalog for --catalog
+    Snapshot {
+        #[arg(
+            long = "demand",
+            value_name = "RealWorldDataBrewingDemand JSON-LD URL (or JSON text)"
+        )]
+        demand: String,
+        #[arg(
+            long = "output",
+            value_name = "Catalog directory to write"
+        )]
+        output: PathBuf,
+    },
 }
 
-fn brewing_data_sample(
-    pattern: &str,
-    data_set_base_path: &str,
-    brewing_arguments: &Vec<Map<String, Value>>,
+#[allow(clippy::too_many_arguments)]
+async fn brewing_data_sample(
     output_path: &str,
-    dt_start: &DateTime<Local>,
-    dt_end: &DateTime<Local>,
-    duration: &Duration,
-) -> Result<(), Box<dyn std::error::Error>> {
-    match pattern {
-        "%Y/%Y-%m-%d.extention" => {
-            match data_set_base_path {
-                _ if data_set_base_path.starts_with(protocols::FILE) => { 
-                    let file_path = data_set_base_path.to_string().replace(protocols::FILE, "");
-                    let mut dt = *dt_start;
-                    while dt <= *dt_end {
-                            let year_str = dt.format("%Y").to_string();
-                            let date_str = dt.format("%Y-%m-%d").to_string();
-                            let data_set_path = format!("{}{}/{}.extention",file_path, year_str, date_str);
-                            info!("data_set_path: {}", data_set_path);
-                            let file = std::fs::File::open(&data_set_path).map_err(|e| {
-                                eprintln!("Error opening file {}: {}", data_set_path, e);
-                                Box::<dyn std::error::Error>::from("Error: Unable to open file")
-                            })?;
-                            let mut brewed_data: String = String::new();
-                            for arg in brewing_arguments {
-                                brewed_data = data_brewer_micro::data_brewer_sample(&file, arg.clone()).map_err(|e| {
-                                    eprintln!("Error brewing data: {}", e);
-                                    Box::<dyn std::error::Error>::from("Error: Unable to brew data")
-                                })?;
-                            }
-
-                            info!("brewed_data: {:?}", brewed_data);
-                            match output_path {
-                                _ if output_path.starts_with(protocols::FILE) => {
-                                    info!("output_path: {}", output_path);
-                                    let output_file_path = format!("{}{}/{}.extention", output_path, year_str, date_str);
-                                    utils::mkdir_to_dest(&output_file_path, *dt_start, *dt_end, *duration);
-                                    let output_path = output_file_path.to_string().replace(protocols::FILE, "");
-                                    std::fs::write(&output_path, brewed_data).map_err(|e| {
-                                        eprintln!("Error writing file {}: {}", output_path, e);
-                                        Box::<dyn std::error::Error>::from("Error: Unable to write file")
-                                    })?;
-                                }
-                                // _ if  data_set_base_path.starts_with(protocols::FTP) => {},   // TODO: Implement this!
-                                // _ if  data_set_base_path.starts_with(protocols::HTTP) => {},  // TODO: Implement this!
-                                // _ if  data_set_base_path.starts_with(protocols::HTTPS) => {}, // TODO: Implement this!
-                                _ => {
-                                    eprintln!("Error: Unknown output_path protocol");
-                                    return Err("Error: Unknown output_path protocol".into());
-                                }
-                            }
-
-                            dt += *duration;
-                        }
-                        Ok(())
-                    }
-                // _ if  data_set_base_path.starts_with(protocols::FTP) => {},   // TODO: Implement this!
-                // _ if  data_set_base_path.starts_with(protocols::HTTP) => {},  // TODO: Implement this!
-                // _ if  data_set_base_path.starts_with(protocols::HTTPS) => {}, // TODO: Implement this!
-                _ => {
-                    eprintln!("Error: Unknown data_set_pattern");
-                    return Err("Error: Unknown data_set_pattern".into());
-                }
-            }
+    plan: &BrewPlan,
+    distribution: &DistributionPlan,
+    values: &Values,
+    reporter: &StatusReporter,
+    progress: &mut Progress,
+    lineage: &mut LineageRecorder,
+) -> Result<Option<(DateTime<Tz>, DateTime<Tz>)>, Box<dyn std::error::Error>> {
+    let data_set_base_path = distribution.base_url.as_str();
+    match data_set_base_path {
+        _ if data_set_base_path.starts_with(protocols::FILE) => {}
+        // _ if  data_set_base_path.starts_with(protocols::FTP) => {},   // TODO: Implement this!
+        // _ if  data_set_base_path.starts_with(protocols::HTTP) => {},  // TODO: Implement this!
+        // _ if  data_set_base_path.starts_with(protocols::HTTPS) => {}, // TODO: Implement this!
+        _ => {
+            eprintln!("Error: Unknown data_set_base_path protocol");
+            return Err("Error: Unknown data_set_base_path protocol".into());
         }
+    }
+    match output_path {
+        _ if output_path.starts_with(protocols::FILE) => {}
+        // _ if  output_path.starts_with(protocols::FTP) => {},   // TODO: Implement this!
+        // _ if  output_path.starts_with(protocols::HTTP) => {},  // TODO: Implement this!
+        // _ if  output_path.starts_with(protocols::HTTPS) => {}, // TODO: Implement this!
         _ => {
-            eprintln!("Error: Unknown data_set_pattern");
-            return Err("Error: Unknown data_set_pattern".into());
+            eprintln!("Error: Unknown output_path protocol");
+            return Err("Error: Unknown output_path protocol".into());
         }
     }
-}
 
+    let brewer = distribution.brewer.as_ref().ok_or("Error: No brewer for the distribution")?;
+    // An explicit --input_encoding wins over the declared charset.
+    let charset = plan.encoding.input.or(distribution.charset);
+    let mut brewed_range: Option<(DateTime<Tz>, DateTime<Tz>)> = None;
+    for &dt in &plan.slots {
+        let output_slot_path = format!("{}{}", output_path, utils::format_time(&dt, &plan.output_pattern)?);
+        if !distribution.covers(dt, plan.unit) {
+            let output_file_path = placeholders::fill(&output_slot_path, values, str::to_string)?;
+            if plan.policy != OutOfWindowPolicy::Fill {
+                debug!("Skipping {} outside the window of {}", dt.to_rfc3339(), data_set_base_path);
+            } else if !placeholders::names(&output_file_path).is_empty() {
+                // Which files to fill is only known from the inputs.
+                warn!("Cannot fill {} outside the availability window", output_file_path);
+            } else {
+                fill_output(&output_file_path, plan)?;
+                progress.done += 1;
+                reporter.report_progress(progress).await;
+            }
+            continue;
+        }
+        let slot_inputs = input_matcher::match_slot(data_set_base_path, &distribution.pattern, &dt, values).map_err(|e| {
+            eprintln!("Error matching input files: {}", e);
+            Box::<dyn std::error::Error>::from("Error: Unable to open file")
+        })?;
+        // One output file per combination of placeholder values.
+        for slot_input in slot_inputs {
+            let output_file_path = placeholders::fill(&output_slot_path, &slot_input.values, str::to_string)?;
+            if let Some(name) = placeholders::names(&output_file_path).first() {
+                eprintln!("Error: No value for placeholder {{{}}} of {}", name, output_file_path);
+                return Err("Error: Unfilled output placeholder".into());
+            }
+            info!("data_set_paths: {:?}", slot_input.paths);
+            let brewed_data = brewer.brew(&slot_input.paths, plan.match_mode, charset)?;
+
+            debug!("brewed_data: {} bytes", brewed_data.len());
+            info!("output_path: {}", output_path);
+            utils::mkdir_to_dest(&output_file_path);
+            let output_fs_path = output_file_path.replace(protocols::FILE, "");
+            let encoded = text_encoding::encode(&brewed_data, &plan.encoding);
+            compression::write(&output_fs_path, &encoded, plan.output_compression).map_err(|e| {
+                eprintln!("Error writing file {}: {}", output_fs_path, e);
+                Box::<dyn std::error::Error>::from("Error: Unable to write file")
+            })?;
+            let input_urls: Vec<String> = slot_input
+                .paths
+                .iter()
+                .map(|path| format!("{}{}", protocols::FILE, path.display()))
+                .collect();
+            lineage.record(&input_urls, output_path, &output_file_path)?;
+        }
+
+        brewed_range = Some((brewed_range.map_or(dt, |(start, _)| start), plan.unit.add(dt, 1)));
+        progress.done += 1;
+        reporter.report_progress(progress).await;
+    }
+    Ok(brewed_range)
+}
 
+/// Builds the resolver every `@ref` and remote `@context` of a run goes
+/// through, so that documents shared between demands are fetched once.
+pub fn demand_resolver(options: &DemandOptions) -> Result<Arc<RefResolver>, Box<dyn std::error::Error>> {
+    let catalog = options.catalog.as_deref().map(Catalog::open).transpose()?;
+    Ok(Arc::new(RefResolver::new(options.ref_cache_dir.clone(), catalog, options.offline)))
+}
 
-async fn process_demand(json_ld: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let loaded_json_ld: serde_json::Map<String, serde_json::Value> = if json_ld.starts_with("http") {
-        load_json_ld(json_ld, 6, false).await
-        .unwrap_or_else(|e| {
+/// Fetches or parses a demand, inlines its `@ref`s, normalizes its keys and
+/// checks that it is addressed to this brewer. `demand_url` is updated with
+/// the demand's own URL as soon as it is known.
+async fn load_demand(
+    json_ld: &str,
+    options: &DemandOptions,
+    resolver: &Arc<RefResolver>,
+    demand_url: &mut Option<String>,
+) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+    let mut demand = if json_ld.starts_with("http") {
+        resolver.fetch(json_ld).await.map_err(|e| {
             eprintln!("Failed to load JSON-LD: {}", e);
-            std::process::exit(1);
-        })
+            e
+        })?
     } else {
-        let mut loaded_json_ld = serde_json::from_str::<serde_json::Map<String, Value>>(json_ld)
-        .map_err(|e| { 
+        serde_json::from_str::<Value>(json_ld).map_err(|e| {
             eprintln!("Failed to parse JSON-LD: {}", e);
             Box::<dyn std::error::Error>::from(e)
-        })?;
-        scan_json_ld_obj(&mut loaded_json_ld, 6, false).await; // Call here for non-http case
-        loaded_json_ld
+        })?
+    };
+    if let Some(url) = status_reporter::demand_url(&demand) {
+        *demand_url = Some(url);
+    }
+    resolver.inline_refs(&mut demand, options.max_depth).await;
+    let loaded_json_ld = match demand {
+        Value::Object(obj) => obj,
+        _ => return Err("Error: Demand JSON-LD is not an object".into()),
     };
-    
+
+    let loaded_json_ld = match json_ld_context::normalize(&Value::Object(loaded_json_ld), resolver).await {
+        Ok(Value::Object(obj)) => obj,
+        Ok(_) => return Err("Error: Demand JSON-LD is not an object".into()),
+        Err(e) => {
+            eprintln!("Failed to process @context: {}", e);
+            return Err(e.into());
+        }
+    };
+
     info!(
         "{} Scanned Message: {:?}",
         DBP_RWD_BREWING_DEMAND, loaded_json_ld
     );
 
-    let brewing_schema_name = loaded_json_ld[DBP_BREWER_INFO][SC_NAME].as_str().unwrap_or_else(||{
-        eprintln!("Failed to extract brewing schema name");
-        std::process::exit(1);
-    });
+    // A demand explicitly addressed to another brewer is left alone unless it
+    // was claimed; anything else is ours to validate and report on.
+    if let Some(brewing_schema_name) = loaded_json_ld
+        .get(DBP_BREWER_INFO)
+        .and_then(|v| v.get(SC_NAME))
+        .and_then(|v| v.as_str())
+    {
+        if brewing_schema_name != options.brewer_name {
+            println!("This is NOT demand for this program");
+            return Err("This is NOT demand for this program".into());
+        }
+    }
+    info!("This is demand for DBP-BrewerTemplate program");
+    Ok(loaded_json_ld)
+}
+
+pub async fn process_demand(
+    json_ld: &str,
+    options: &DemandOptions,
+    resolver: &Arc<RefResolver>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut demand_url = Some(json_ld.to_string()).filter(|url| url.starts_with("http"));
+    let loaded_json_ld = match load_demand(json_ld, options, resolver, &mut demand_url).await {
+        Ok(loaded_json_ld) => loaded_json_ld,
+        Err(e) => {
+            // A claimed demand is already accepted; leaving it there would
+            // keep every other worker away from it.
+            if options.report.accepted {
+                StatusReporter::new(&options.report, demand_url)
+                    .report(DemandState::Failed, &Progress::default(), Some(&e.to_string()), None)
+                    .await;
+            }
+            return Err(e);
+        }
+    };
+
+    let demand_url = status_reporter::demand_url(&Value::Object(loaded_json_ld.clone())).or(demand_url);
+    let reporter = StatusReporter::new(&options.report, demand_url);
+    let mut progress = Progress::default();
+
+    let demand = match BrewingDemand::from_json_ld(
+        &loaded_json_ld,
+        options.parse_mode,
+        resolver.clone(),
+        options.provenance_depth,
+        options.timezone,
+    ).await {
+        Ok(demand) => demand,
+        Err(errors) => {
+            reporter.report(DemandState::Failed, &progress, Some(&errors.to_string()), None).await;
+            return Err(Box::new(errors));
+        }
+    };
+    info!(
+        "Accepted demand {} for brewer {} ({})",
+        demand.id.as_deref().unwrap_or("<no @id>"),
+        demand.brewer_info.name,
+        demand.brewer_info.id.as_deref().unwrap_or("<no @id>")
+    );
+    if options.dry_run {
+        let plan = plan_demand(&demand, options)?;
+        println!("Brewing plan for {}:\n{}", demand.id.as_deref().unwrap_or("<no @id>"), plan);
+        if options.completeness != CompletenessFormat::Off {
+            CompletenessReport::new("before", &demand, &plan).print(options.completeness);
+        }
+        return plan.check().map_err(|e| {
+            eprintln!("{}", e);
+            e.into()
+        });
+    }
+    if !options.report.accepted {
+        reporter.report(DemandState::Accepted, &progress, None, None).await;
+    }
+
+    match brew_demand(&demand, options, &reporter, &mut progress).await {
+        Ok(output) => {
+            let output_ref = describe_output(&demand, &output, options).await;
+            reporter.report(DemandState::Succeeded, &progress, None, Some(&output_ref)).await;
+            Ok(())
+        }
+        Err(e) => {
+            reporter.report(DemandState::Failed, &progress, Some(&e.to_string()), None).await;
+            Err(e)
+        }
+    }
+}
+
+/// Writes the provenance RealWorldDataset for a finished brew, optionally
+/// publishes it, and returns the reference reported as the demand's output.
+async fn describe_output(
+    demand: &BrewingDemand,
+    output: &BrewedOutput,
+    options: &DemandOptions,
+) -> String {
+    let dataset = provenance::to_json_ld(
+        &provenance::build_output_dataset(demand, &output.base_url, &output.pattern, output.range),
+        demand,
+    );
+    if let Err(e) = provenance::write_next_to_output(&dataset, &output.base_url) {
+        error!("Failed to write output RealWorldDataset: {}", e);
+    }
+    if let Some(publish_url) = &options.publish_dataset_url {
+        match provenance::publish(&dataset, publish_url).await {
+            Ok(Some(id)) => return id,
+            Ok(None) => {}
+            Err(e) => error!("Failed to publish output RealWorldDataset: {}", e),
+        }
+    }
+    output.base_url.clone()
+}
 
-    match brewing_schema_name {
-        "dbpBrewerTemplate" => {
-            info!("This is demand for DBP-BrewerTemplate program");
+/// Writes an empty output file for a slot outside a distribution's window.
+fn fill_output(output_file_path: &str, plan: &BrewPlan) -> Result<(), Box<dyn std::error::Error>> {
+    if !output_file_path.starts_with(protocols::FILE) {
+        return Err("Error: Unknown output_path protocol".into());
+    }
+    utils::mkdir_to_dest(output_file_path);
+    let output_fs_path = output_file_path.replace(protocols::FILE, "");
+    info!("Filling {} outside the availability window", output_fs_path);
+    compression::write(&output_fs_path, b"", plan.output_compression).map_err(|e| {
+        eprintln!("Error writing file {}: {}", output_fs_path, e);
+        Box::<dyn std::error::Error>::from("Error: Unable to write file")
+    })?;
+    Ok(())
+}
 
-            // Extract brewing arguments
-            let brewing_arguments= match utils::get_brewing_arguments(&loaded_json_ld){
-                Ok(brewing_arguments) => brewing_arguments,
-                Err(e) => {
-                    eprintln!("Failed to extract brewing_arguments: {}", e);
-                    return Err(e);
-                }
+/// Works out the slots a validated demand will brew for each distribution.
+fn plan_demand(demand: &BrewingDemand, options: &DemandOptions) -> Result<BrewPlan, Box<dyn std::error::Error>> {
+    let tz = demand.time_zone;
+    let unit = utils::extract_minimum_unit(demand.output_pattern())
+        .ok_or("Error: Invalid output pattern")?;
+    let given = (demand.time_period_start, demand.time_period_end);
+    let (dt_start, dt_end, present) = match given {
+        (Some(start), Some(end)) => (start.with_timezone(&tz), end.with_timezone(&tz), None),
+        (start, end) => {
+            // Auto mode: omitted bounds come from the slots present in the inputs.
+            let present = present_slots(demand, tz)?;
+            let (Some(&first), Some(&last)) = (present.first(), present.last()) else {
+                return Err("Error: No input slots found".into());
             };
-            info!("brewing_arguments: {:?}", brewing_arguments);
-
-            // Extract output information
-            let output_path = loaded_json_ld[DBP_BREWER_OUTPUT_STORE][DBP_BASE_URL]
-                .as_str()
-                .ok_or("Error: DBP_BASE_URL is missing")?;
-            let data_output_path_pattern = loaded_json_ld[DBP_BREWER_OUTPUT_STORE][DBP_PATTERN]
-                .as_str()
-                .ok_or("Error: DBP_PATTERN is missing")?;
-            let dt_start: DateTime<Local> = Into::into(
-                DateTime::parse_from_rfc3339(
-                    loaded_json_ld[DBP_TIME_PERIOD_START]
-                    .as_str()
-                    .ok_or("Error: DBP_TIME_PERIOD_START is missing")?
-                ).map_err(|_| "Error: Invalid date format for DBP_TIME_PERIOD_START")?
-            );
-            let dt_end: DateTime<Local> = Into::into(
-                DateTime::parse_from_rfc3339(
-                    loaded_json_ld[DBP_TIME_PERIOD_END]
-                    .as_str()
-                    .ok_or("Error: DBP_TIME_PERIOD_END is missing")?
-                ).map_err(|_| "Error: Invalid date format for DBP_TIME_PERIOD_END")?
+            let last = match options.interval {
+                IntervalMode::Closed => last,
+                IntervalMode::HalfOpen => unit.add(last, 1),
+            };
+            let dt_start = start.map_or(first, |start| start.with_timezone(&tz));
+            let dt_end = end.map_or(last, |end| end.with_timezone(&tz));
+            info!("Brewing the slots present in the inputs: {} .. {}", dt_start.to_rfc3339(), dt_end.to_rfc3339());
+            (dt_start, dt_end, Some(present))
+        }
+    };
+    for (key, given, evaluated) in [
+        (DBP_TIME_PERIOD_START, demand.time_period_start, dt_start),
+        (DBP_TIME_PERIOD_END, demand.time_period_end, dt_end),
+    ] {
+        let Some(given) = given else { continue };
+        if given.offset().local_minus_utc() != evaluated.offset().fix().local_minus_utc() {
+            warn!(
+                "{} {} has offset {} but paths are evaluated in {} ({})",
+                key, given.to_rfc3339(), given.offset(), tz.name(), evaluated.to_rfc3339()
             );
-            let duration = utils::extract_minimum_unit(data_output_path_pattern)
-                .ok_or("Error: Invalid output pattern")?;
+        }
+    }
+    let mut plan = BrewPlan::new(
+        demand,
+        dt_start,
+        dt_end,
+        unit,
+        options.interval,
+        options.alignment,
+        options.match_mode,
+        options.out_of_window,
+        &options.accept_formats,
+        options.output_compression,
+        options.encoding,
+    );
+    if let Some(present) = &present {
+        // Auto mode brews what is there and leaves the gaps alone.
+        plan.keep_present(demand, &options.accept_formats, present);
+    }
+    Ok(plan)
+}
 
-            info!("output_path: {}", output_path);
-            info!("data_output_path_pattern: {}", data_output_path_pattern);
-            info!("dt_start: {}", dt_start);
-            info!("dt_end: {}", dt_end);
-            info!("duration: {:?}", duration);
+/// Every slot present in some readable input distribution, for demands
+/// that omit `dbp:timePeriodStart` or `dbp:timePeriodEnd`.
+fn present_slots(demand: &BrewingDemand, tz: Tz) -> Result<BTreeSet<DateTime<Tz>>, Box<dyn std::error::Error>> {
+    let mut present = BTreeSet::new();
+    for input in &demand.inputs {
+        let values = placeholders::known_values(demand, input);
+        for distribution in &input.distributions {
+            let base_url = distribution.info.base_url.as_deref().unwrap_or_default();
+            let pattern = distribution.info.pattern.as_deref().unwrap_or_default();
+            match Inventory::scan(base_url, pattern, tz, &values) {
+                Ok(inventory) => {
+                    info!("Discovered {}", inventory);
+                    present.extend(inventory.slots);
+                }
+                Err(e) => warn!("Cannot discover the slots of {}: {}", base_url, e),
+            }
+        }
+    }
+    if present.is_empty() {
+        eprintln!("Error: No input slots found; give {} and {}", DBP_TIME_PERIOD_START, DBP_TIME_PERIOD_END);
+        return Err("Error: No input slots found".into());
+    }
+    Ok(present)
+}
 
+/// Brews every input distribution of a validated demand.
+async fn brew_demand(
+    demand: &BrewingDemand,
+    options: &DemandOptions,
+    reporter: &StatusReporter,
+    progress: &mut Progress,
+) -> Result<BrewedOutput, Box<dyn std::error::Error>> {
+    let brewing_arguments = demand.sample_arguments();
+    info!("brewing_arguments: {:?}", brewing_arguments);
 
-            // Extract data_sets
-            let data_sets = match extract_data_sets(&loaded_json_ld) {
-                Ok(datasets) => datasets,
-                Err(e) => {
-                    eprintln!("Failed to extract datasets: {}", e);
-                    return Err(e);
+    let output_path = demand.output_base_url();
+    let data_output_path_pattern = demand.output_pattern();
+    let plan = plan_demand(demand, options)?;
+
+    info!("output_path: {}", output_path);
+    info!("data_output_path_pattern: {}", data_output_path_pattern);
+    info!("brewer_inputs: {:?}", demand.inputs);
+    info!("Brewing plan:\n{}", plan);
+    plan.check().map_err(|e| {
+        eprintln!("{}", e);
+        e
+    })?;
+
+    if options.completeness != CompletenessFormat::Off {
+        CompletenessReport::new("before", demand, &plan).print(options.completeness);
+    }
+
+    let mut lineage = LineageRecorder::new(options.lineage, &demand.arguments);
+
+    progress.total = plan.total_slots();
+    reporter.report(DemandState::Running, progress, None, None).await;
+
+    let mut brewed_range: Option<(DateTime<Tz>, DateTime<Tz>)> = None;
+    for (brewer_input, input_plan) in demand.inputs.iter().zip(&plan.inputs) {
+        info!(
+            "brewing dataset: {} ({})",
+            brewer_input.dataset.name.as_deref().unwrap_or("<unnamed>"),
+            brewer_input.dataset.id.as_deref().unwrap_or("<no @id>")
+        );
+        // Try the distributions in order of preference, falling back to the
+        // next mirror when one cannot be reached or read.
+        let done_before = progress.done;
+        let mut last_error: Option<Box<dyn std::error::Error>> = None;
+        for distribution in &input_plan.candidates {
+            let data_set_base_path = distribution.base_url.as_str();
+            if let Err(e) = distribution.probe() {
+                warn!("Distribution {} is unreachable: {}", data_set_base_path, e);
+                last_error = Some(e.into());
+                continue;
+            }
+            info!("data_set_pattern: {}", distribution.pattern);
+            progress.done = done_before;
+            match brewing_data_sample(output_path, &plan, distribution, &input_plan.values, reporter, progress, &mut lineage).await {
+                Ok(range) => {
+                    info!("Sample data processed successfully for {}", data_set_base_path);
+                    brewed_range = utils::merge_ranges(brewed_range, range);
+                    last_error = None;
+                    break;
                 }
-            };
-            info!("data_sets: {:?}", data_sets);
-            
-            for data_set in data_sets {
-                if let Some(data_set_array) = data_set.as_array() {
-                    for data_set_object in data_set_array {
-                        if let Some(data_set_base_path) = data_set_object.get(DBP_BASE_URL).and_then(|v| v.as_str())
-                        {
-                            if let Some(pattern_value) =
-                            data_set_object.get(DBP_PATTERN).and_then(|v| v.as_str())
-                        {
-                            match pattern_value {
-                                pattern if pattern == data_output_path_pattern => {
-                                    if pattern.ends_with(".extention") {
-                                        info!("data_set_pattern: {}", pattern);
-                                        match brewing_data_sample(pattern, data_set_base_path, &brewing_arguments, output_path, &dt_start, &dt_end, &duration) {
-                                            Ok(_) => {
-                                                info!("Sample data processed successfully for {}", data_set_base_path);
-                                            }
-                                            Err(e) => {
-                                                error!("Error processing Sample data for {}: {}", data_set_base_path, e);
-                                                return Err(e);
-                                            }
-                                        }
-                                    } else {
-                                        eprintln!("Error: Unknown data_set_pattern");
-                                        return Err("Error: Unknown data_set_pattern".into());
-                                    }
-                                }
-                                _ => {
-                                    info!("data_set_pattern: {} does not match data_output_path_pattern: {}", pattern_value, data_output_path_pattern);
-                                    return Err("Error: data_set_pattern does not match data_output_path_pattern".into()); 
-                                }
-                            }
-                        } else {
-                            eprintln!("Error: DBP_BASE_URL is missing");
-                            return Err("Error: DBP_BASE_URL is missing".into());
-                        }
-
-                        } else {
-                            eprintln!("Error: DBP_PATTERN is missing");
-                            return Err("Error: DBP_PATTERN is missing".into());
-                        }
-                    }
+                Err(e) => {
+                    warn!("Error processing Sample data for {}: {}", data_set_base_path, e);
+                    last_error = Some(e);
                 }
             }
-
-            Ok(())
         }
-        _ => {
-            println!("This is NOT demand for this program");
-            return Err("This is NOT demand for this program".into());
+        if let Some(e) = last_error {
+            progress.failed += 1;
+            error!("No distribution of {} could be brewed: {}", input_plan.dataset, e);
+            return Err(e);
         }
     }
+
+    lineage.finish(output_path)?;
+
+    if options.completeness != CompletenessFormat::Off {
+        CompletenessReport::new("after", demand, &plan).print(options.completeness);
+    }
+
+    Ok(BrewedOutput {
+        base_url: output_path.to_string(),
+        pattern: data_output_path_pattern.to_string(),
+        range: brewed_range,
+    })
 }
 
 #[tokio::main]
@@ -260,8 +770,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let start_time = Local::now();
     info!("Started Program at {}", start_time.format("%F %T %:z"));
 
-    println!("Received json_ld: {}", args.json_ld);
-    process_demand(args.json_ld.as_str()).await?;
+    let input_encoding = args.input_encoding.as_deref().map(text_encoding::lookup).transpose();
+    let encoding = match (input_encoding, text_encoding::lookup(&args.output_encoding)) {
+        (Ok(input), Ok(output)) => TextEncoding { input, output, output_bom: args.output_bom },
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Error: {}", e);
+            return Err(e.into());
+        }
+    };
+    let demand_options = DemandOptions {
+        brewer_name: BREWER_NAME.to_string(),
+        report: ReportOptions {
+            report_status: args.report_status,
+            callback_url: args.status_callback_url,
+            accepted: false,
+        },
+        publish_dataset_url: args.publish_dataset_url,
+        lineage: args.lineage,
+        parse_mode: args.parse_mode,
+        ref_cache_dir: args.ref_cache_dir,
+        max_depth: args.max_depth,
+        provenance_depth: args.provenance_depth,
+        catalog: args.catalog,
+        offline: args.offline,
+        out_of_window: args.out_of_window,
+        accept_formats: args.accept_format,
+        timezone: args.timezone,
+        interval: args.interval,
+        alignment: args.align,
+        match_mode: args.match_mode,
+        completeness: args.completeness,
+        output_compression: args.output_compression,
+        encoding,
+        dry_run: args.dry_run,
+    };
+    match args.command {
+        Some(Command::Worker { .. }) if demand_options.offline => {
+            eprintln!("Error: The worker polls the demand list API and cannot run with --offline");
+            return Err("Error: The worker cannot run with --offline".into());
+        }
+        Some(Command::Worker { demand_list_url, brewer_name, poll_interval, max_backoff, once }) => {
+            let config = worker::WorkerConfig {
+                demand_list_url,
+                poll_interval: StdDuration::from_secs(poll_interval),
+                max_backoff: StdDuration::from_secs(max_backoff),
+                once,
+                demand_options: DemandOptions { brewer_name, ..demand_options },
+            };
+            worker::run(&config).await?;
+        }
+        Some(Command::Verify { output, check_inputs }) => {
+            match lineage::verify(&output, check_inputs) {
+                Ok(checked) => println!("Verified {} lineage entries under {}", checked, output),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return Err("Error: Lineage verification failed".into());
+                }
+            }
+        }
+        Some(Command::Discover { base_url, pattern }) => {
+            let inventory = Inventory::scan(&base_url, &pattern, demand_options.timezone, &Values::new()).map_err(|e| {
+                eprintln!("{}", e);
+                Box::<dyn std::error::Error>::from("Error: Unable to discover time slots")
+            })?;
+            println!("{}", inventory);
+        }
+        Some(Command::Snapshot { demand, output }) => {
+            let written = catalog::snapshot(&demand, &output).await?;
+            println!("Saved {} documents to catalog {}", written, output.display());
+        }
+        None => {
+            println!("Received json_ld: {}", args.json_ld);
+            process_demand(args.json_ld.as_str(), &demand_options, &demand_resolver(&demand_options)?).await?;
+        }
+    }
     let finish_time = Local::now();
     info!("Finished Program at {}", finish_time.format("%F %T %:z"));
     Ok(())
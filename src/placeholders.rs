@@ -0,0 +1,229 @@
+// Standard Library
+use std::collections::BTreeMap;
+
+// External Library
+use regex::Regex;
+
+use crate::brewing_demand::{BrewerInput, BrewingDemand};
+
+/// Values of named `{placeholder}`s, by name.
+pub type Values = BTreeMap<String, String>;
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap_or_else(|e| {
+        eprintln!("Failed to compile regex: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Names of the `{placeholder}`s in a pattern, in order of appearance.
+pub fn names(pattern: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for capture in placeholder_regex().captures_iter(pattern) {
+        if !names.iter().any(|name| name == &capture[1]) {
+            names.push(capture[1].to_string());
+        }
+    }
+    names
+}
+
+/// Replaces every placeholder that has a value, passing each value through
+/// `escape` first; unknown placeholders are left as written. Values fill a
+/// single path segment, so one holding `/` or `..` is an error rather than
+/// a way out of the base directory; `%` is refused as it would be read as
+/// a time specifier.
+pub fn fill(pattern: &str, values: &Values, escape: impl Fn(&str) -> String) -> Result<String, String> {
+    let placeholders = placeholder_regex();
+    for capture in placeholders.captures_iter(pattern) {
+        if let Some(value) = values.get(&capture[1]) {
+            if value.contains(['/', '%']) || value.contains("..") {
+                return Err(format!("Value {:?} of placeholder {} must not contain /, .. or %", value, &capture[0]));
+            }
+        }
+    }
+    Ok(placeholders
+        .replace_all(pattern, |capture: &regex::Captures| match values.get(&capture[1]) {
+            Some(value) => escape(value),
+            None => capture[0].to_string(),
+        })
+        .to_string())
+}
+
+/// Values known before anything is read: `dataset_id` (the last segment of
+/// the input dataset's `@id`), `dataset_name` (its `schema:name`), and every
+/// brewing argument by its `dbp:key`, which wins over the metadata.
+pub fn known_values(demand: &BrewingDemand, input: &BrewerInput) -> Values {
+    let mut values = Values::new();
+    if let Some(id) = &input.dataset.id {
+        if let Some(segment) = id.trim_end_matches('/').rsplit('/').next() {
+            values.insert("dataset_id".to_string(), segment.to_string());
+        }
+    }
+    if let Some(name) = &input.dataset.name {
+        values.insert("dataset_name".to_string(), name.clone());
+    }
+    for arg in &demand.arguments {
+        values.insert(arg.key.clone(), arg.value.clone());
+    }
+    values
+}
+
+/// Translates a path pattern into an anchored regex with one group per
+/// `{placeholder}`, and with `time_fields` one per `%Y`, `%m`, `%d`, `%H`,
+/// `%M` and `%S` as well. Wildcards and character classes match as they do
+/// in the glob. Returns the regex and what each group captures: the
+/// placeholder name, or the time specifier such as `%Y`.
+pub fn pattern_regex(pattern: &str, time_fields: bool) -> Result<(Regex, Vec<String>), String> {
+    let placeholders = placeholder_regex();
+    let mut groups = Vec::new();
+    let mut source = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        let mut len = c.len_utf8();
+        match c {
+            '*' => source.push_str("[^/]*"),
+            '?' => source.push_str("[^/]"),
+            '[' => {
+                // As in the glob, a `]` right after `[` or `[!` is a member,
+                // so `glob::Pattern::escape`'s `[[]` and `[]]` read back.
+                let negated = rest[1..].starts_with('!');
+                let start = if negated { 2 } else { 1 };
+                let first = rest[start..].chars().next().map_or(0, char::len_utf8);
+                match rest[start..].get(first..).and_then(|after| after.find(']')) {
+                    Some(offset) if first > 0 => {
+                        let end = start + first + offset;
+                        let members = class_members(&rest[start..end]);
+                        source.push_str(&format!("[{}{}]", if negated { "^" } else { "" }, members));
+                        len = end + 1;
+                    }
+                    _ => source.push_str(&regex::escape("[")),
+                }
+            }
+            '{' => match placeholders.captures(rest).filter(|capture| capture.get(0).is_some_and(|m| m.start() == 0)) {
+                Some(capture) => {
+                    source.push_str("([^/]+)");
+                    groups.push(capture[1].to_string());
+                    len = capture[0].len();
+                }
+                None => source.push_str(&regex::escape("{")),
+            },
+            '%' if time_fields => {
+                let specifier = rest[1..].chars().next().ok_or_else(|| format!("Pattern {} ends with %", pattern))?;
+                match specifier {
+                    'Y' => source.push_str(r"(\d{4})"),
+                    'm' | 'd' | 'H' | 'M' | 'S' => source.push_str(r"(\d{2})"),
+                    '%' => source.push('%'),
+                    c => return Err(format!("Unsupported time specifier %{} in pattern {}", c, pattern)),
+                }
+                if specifier != '%' {
+                    groups.push(format!("%{}", specifier));
+                }
+                len = 1 + specifier.len_utf8();
+            }
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+        rest = &rest[len..];
+    }
+    source.push('$');
+    let regex = Regex::new(&source).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+    Ok((regex, groups))
+}
+
+/// The members of a glob character class as regex class syntax: ranges are
+/// kept and everything the regex crate gives a meaning inside a class is
+/// escaped.
+fn class_members(members: &str) -> String {
+    let mut escaped = String::with_capacity(members.len());
+    for c in members.chars() {
+        if matches!(c, '\\' | '[' | ']' | '^' | '&' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Values of each group of a `pattern_regex` match, or `None` if the path
+/// does not match or a group appearing twice took different values.
+pub fn capture_groups(regex: &Regex, groups: &[String], path: &str) -> Option<Values> {
+    let captures = regex.captures(path)?;
+    let mut values = Values::new();
+    for (name, m) in groups.iter().zip(captures.iter().skip(1)) {
+        let value = m?.as_str();
+        if values.get(name).is_some_and(|seen| seen != value) {
+            return None;
+        }
+        values.insert(name.clone(), value.to_string());
+    }
+    Some(values)
+}
+
+/// Matches input paths, relative to the distribution's base, against a
+/// pattern whose time placeholders are already expanded, capturing the
+/// placeholders that had no value.
+pub struct Discovery {
+    regex: Regex,
+    /// Placeholder captured by each group, in order.
+    groups: Vec<String>,
+}
+
+impl Discovery {
+    pub fn new(relative_pattern: &str) -> Result<Self, String> {
+        let (regex, groups) = pattern_regex(relative_pattern, false)?;
+        Ok(Discovery { regex, groups })
+    }
+
+    /// The value of every unfilled placeholder, or `None` if the path does
+    /// not fit the pattern.
+    pub fn capture(&self, relative_path: &str) -> Option<Values> {
+        capture_groups(&self.regex, &self.groups, relative_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_values_read_back() {
+        let values: Values = [("site".to_string(), "a[1]*?".to_string())].into_iter().collect();
+        let filled = fill("{site}/%Y.csv", &values, glob::Pattern::escape).unwrap();
+        assert_eq!(filled, "a[[]1[]][*][?]/%Y.csv");
+        let (regex, groups) = pattern_regex(&filled, true).unwrap();
+        assert_eq!(groups, vec!["%Y"]);
+        assert!(regex.is_match("a[1]*?/2023.csv"));
+        assert!(!regex.is_match("a[1]xy/2023.csv"));
+    }
+
+    #[test]
+    fn character_classes_match_as_in_the_glob() {
+        let cases = [
+            ("[0-9].csv", "7.csv", true),
+            ("[0-9].csv", "x.csv", false),
+            ("[!0-9].csv", "x.csv", true),
+            ("[!0-9].csv", "7.csv", false),
+            ("[]a].csv", "].csv", true),
+            ("[!]].csv", "].csv", false),
+            ("[^&~].csv", "&.csv", true),
+            ("[é].csv", "é.csv", true),
+            ("[a.csv", "[a.csv", true),
+            ("[].csv", "[].csv", true),
+        ];
+        for (pattern, path, expected) in cases {
+            let (regex, _) = pattern_regex(pattern, false).unwrap();
+            assert_eq!(regex.is_match(path), expected, "{} against {}", pattern, path);
+            let glob_matches = glob::Pattern::new(pattern).map_or(expected, |glob| glob.matches(path));
+            assert_eq!(glob_matches, expected, "glob {} against {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn values_leaving_their_segment_are_rejected() {
+        for value in ["a/b", "..", "../etc", "x..y", "100%", "%Y", "%Q"] {
+            let values: Values = [("site".to_string(), value.to_string())].into_iter().collect();
+            assert!(fill("{site}/%Y.csv", &values, str::to_string).is_err(), "{}", value);
+        }
+        let values: Values = [("site".to_string(), "a.b".to_string())].into_iter().collect();
+        assert_eq!(fill("{site}/{other}", &values, str::to_string).unwrap(), "a.b/{other}");
+    }
+}
@@ -0,0 +1,552 @@
+// Standard Library
+use std::collections::BTreeSet;
+use std::fmt;
+
+// External Library
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+
+use crate::brewer::{Brewer, BrewerKind};
+use crate::brewing_demand::{BrewerInput, BrewingDemand, Distribution};
+use crate::compression::{self, Compression};
+use crate::input_matcher::MatchMode;
+use crate::placeholders::{self, Values};
+use crate::protocols;
+use crate::slots::{self, Alignment, IntervalMode, SlotUnit};
+use crate::text_encoding::{self, TextEncoding};
+use crate::time_parser;
+use crate::utils;
+
+/// What to do with requested slots outside a distribution's declared
+/// `dbp:startTime` / `dbp:endTime` window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutOfWindowPolicy {
+    /// Brew only the slots inside the window
+    #[default]
+    Skip,
+    /// Refuse the demand if any requested slot is outside the window
+    Fail,
+    /// Write an empty output file for slots outside the window
+    Fill,
+}
+
+impl OutOfWindowPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutOfWindowPolicy::Skip => "skip",
+            OutOfWindowPolicy::Fail => "fail",
+            OutOfWindowPolicy::Fill => "fill",
+        }
+    }
+}
+
+const SHOWN_SLOTS: usize = 5;
+
+/// How one input distribution will be brewed.
+#[derive(Clone, Debug)]
+pub struct DistributionPlan {
+    pub base_url: String,
+    pub pattern: String,
+    pub encoding_format: Option<String>,
+    /// Declared charset of the files; detected when `None`.
+    pub charset: Option<&'static Encoding>,
+    pub window_start: Option<DateTime<Tz>>,
+    pub window_end: Option<DateTime<Tz>>,
+    /// Requested period intersected with the window; `None` if they do not overlap.
+    pub effective: Option<(DateTime<Tz>, DateTime<Tz>)>,
+    pub slots_inside: usize,
+    pub slots_outside: usize,
+    /// The brewer configured for the distribution; `None` if it is rejected.
+    pub brewer: Option<Brewer>,
+}
+
+impl DistributionPlan {
+    fn new(distribution: &Distribution, slots: &[DateTime<Tz>], unit: SlotUnit) -> Self {
+        let tz = slots.first().map_or(Tz::UTC, |dt| dt.timezone());
+        let to_local = |t: &prost_types::Timestamp| time_parser::from_timestamp(t).map(|dt| dt.with_timezone(&tz));
+        let info = &distribution.info;
+        let mut plan = DistributionPlan {
+            base_url: info.base_url.clone().unwrap_or_default(),
+            pattern: info.pattern.clone().unwrap_or_default(),
+            encoding_format: distribution.encoding_format.clone(),
+            charset: distribution
+                .encoding_format
+                .as_deref()
+                .and_then(text_encoding::charset)
+                .and_then(|label| text_encoding::lookup(label).ok()),
+            window_start: info.start_time.as_ref().and_then(to_local),
+            window_end: info.end_time.as_ref().and_then(to_local),
+            effective: None,
+            slots_inside: 0,
+            slots_outside: 0,
+            brewer: None,
+        };
+        for &dt in slots {
+            if plan.covers(dt, unit) {
+                plan.slots_inside += 1;
+                plan.effective = Some((plan.effective.map_or(dt, |(start, _)| start), dt));
+            } else {
+                plan.slots_outside += 1;
+            }
+        }
+        plan
+    }
+
+    /// Whether the slot starting at `dt` overlaps the declared window,
+    /// which like the slots themselves excludes its end.
+    pub fn covers(&self, dt: DateTime<Tz>, unit: SlotUnit) -> bool {
+        self.window_start.is_none_or(|start| unit.add(dt, 1) > start)
+            && self.window_end.is_none_or(|end| dt < end)
+    }
+
+    /// Checks that the distribution can be reached right now.
+    pub fn probe(&self) -> Result<(), String> {
+        if self.base_url.starts_with(protocols::FILE) {
+            let path = self.base_url.replace(protocols::FILE, "");
+            if !std::path::Path::new(&path).is_dir() {
+                return Err(format!("{} is not a readable directory", path));
+            }
+        }
+        Ok(())
+    }
+
+    fn protocol_rank(&self) -> Option<usize> {
+        protocols::READABLE.iter().position(|p| self.base_url.starts_with(p))
+    }
+
+    /// Position in the accepted formats; unlabelled distributions come after
+    /// every listed format, and with no preference any format is fine.
+    fn format_rank(&self, accept_formats: &[String]) -> Option<usize> {
+        if accept_formats.is_empty() {
+            return Some(0);
+        }
+        match &self.encoding_format {
+            Some(format) => {
+                let format = text_encoding::media_type(format);
+                accept_formats.iter().position(|f| f.eq_ignore_ascii_case(format))
+            }
+            None => Some(accept_formats.len()),
+        }
+    }
+}
+
+/// The distributions of one input dataset in the order they will be tried,
+/// and those that cannot be used with the reason why.
+#[derive(Clone, Debug)]
+pub struct InputPlan {
+    pub dataset: String,
+    /// Placeholder values known before any input is listed.
+    pub values: Values,
+    pub candidates: Vec<DistributionPlan>,
+    pub rejected: Vec<(String, String)>,
+}
+
+impl InputPlan {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        demand: &BrewingDemand,
+        input: &BrewerInput,
+        values: Values,
+        brewer: BrewerKind,
+        accept_formats: &[String],
+        slots: &[DateTime<Tz>],
+        unit: SlotUnit,
+        policy: OutOfWindowPolicy,
+    ) -> Self {
+        let dataset = input
+            .dataset
+            .name
+            .clone()
+            .or_else(|| input.dataset.id.clone())
+            .unwrap_or_else(|| "<unnamed>".to_string());
+        let output_pattern = demand.output_pattern();
+        let unfilled: Vec<String> = placeholders::names(output_pattern)
+            .into_iter()
+            .filter(|name| !values.contains_key(name))
+            .collect();
+        let mut candidates = Vec::new();
+        let mut rejected = Vec::new();
+        // The structure info's charset applies to distributions that declare none.
+        let structure_charset = input
+            .dataset
+            .structure_info
+            .as_ref()
+            .and_then(|si| si.encoding_format.as_deref())
+            .and_then(text_encoding::charset)
+            .and_then(|label| match text_encoding::lookup(label) {
+                Ok(encoding) => Some(encoding),
+                Err(e) => {
+                    warn!("Ignoring the dbp:structureInfo charset of {}: {}", dataset, e);
+                    None
+                }
+            });
+        for distribution in &input.distributions {
+            let mut plan = DistributionPlan::new(distribution, slots, unit);
+            plan.charset = plan.charset.or(structure_charset);
+            let discovered = placeholders::names(&plan.pattern);
+            let missing: Vec<&String> = unfilled.iter().filter(|name| !discovered.contains(name)).collect();
+            let reason = if plan.protocol_rank().is_none() {
+                Some("protocol is not supported".to_string())
+            } else if utils::extract_minimum_unit(&plan.pattern) != Some(unit) {
+                Some(format!("pattern {} does not have the slots of output pattern {}", plan.pattern, output_pattern))
+            } else if !brewer.extensions().iter().any(|e| compression::strip_extension(&plan.pattern).ends_with(e)) {
+                Some(format!(
+                    "pattern {} is not read by the {} brewer ({})",
+                    plan.pattern,
+                    brewer.as_str(),
+                    brewer.extensions().join(", ")
+                ))
+            } else if !missing.is_empty() {
+                let missing: Vec<String> = missing.iter().map(|name| format!("{{{}}}", name)).collect();
+                Some(format!(
+                    "output placeholder(s) {} are not brewing arguments, dataset metadata or in pattern {}",
+                    missing.join(", "),
+                    plan.pattern
+                ))
+            } else if plan.format_rank(accept_formats).is_none() {
+                Some(format!(
+                    "encodingFormat {} is not one of {}",
+                    plan.encoding_format.as_deref().unwrap_or_default(),
+                    accept_formats.join(", ")
+                ))
+            } else if policy == OutOfWindowPolicy::Fail && plan.slots_outside > 0 {
+                Some(format!("{} slot(s) outside {}", plan.slots_outside, format_window(&plan)))
+            } else {
+                match Brewer::new(brewer, demand, input, plan.encoding_format.as_deref(), &plan.pattern) {
+                    Ok(configured) => {
+                        plan.brewer = Some(configured);
+                        None
+                    }
+                    Err(e) => Some(format!("{} brewer: {}", brewer.as_str(), e)),
+                }
+            };
+            match reason {
+                Some(reason) => rejected.push((plan.base_url, reason)),
+                None => candidates.push(plan),
+            }
+        }
+        // Stable, so mirrors that rank the same keep their declared order.
+        candidates.sort_by_key(|d| (d.format_rank(accept_formats), d.protocol_rank()));
+        InputPlan { dataset, values, candidates, rejected }
+    }
+}
+
+/// The slots a demand will brew, computed before anything is read or written.
+#[derive(Clone, Debug)]
+pub struct BrewPlan {
+    pub brewer: BrewerKind,
+    pub output_pattern: String,
+    pub output_compression: Compression,
+    pub encoding: TextEncoding,
+    pub dt_start: DateTime<Tz>,
+    pub dt_end: DateTime<Tz>,
+    pub unit: SlotUnit,
+    pub interval: IntervalMode,
+    pub alignment: Alignment,
+    pub match_mode: MatchMode,
+    /// Start of every slot to brew, in order.
+    pub slots: Vec<DateTime<Tz>>,
+    /// Whether slots missing from the inputs were dropped (auto mode).
+    pub present_only: bool,
+    pub policy: OutOfWindowPolicy,
+    pub inputs: Vec<InputPlan>,
+}
+
+impl BrewPlan {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        demand: &BrewingDemand,
+        dt_start: DateTime<Tz>,
+        dt_end: DateTime<Tz>,
+        unit: SlotUnit,
+        interval: IntervalMode,
+        alignment: Alignment,
+        match_mode: MatchMode,
+        policy: OutOfWindowPolicy,
+        accept_formats: &[String],
+        output_compression: Option<Compression>,
+        encoding: TextEncoding,
+    ) -> Self {
+        let slots = slots::slots(dt_start, dt_end, unit, interval, alignment);
+        let brewer = BrewerKind::for_demand(demand);
+        let inputs = input_plans(demand, brewer, accept_formats, &slots, unit, policy);
+        BrewPlan {
+            brewer,
+            output_pattern: demand.output_pattern().to_string(),
+            // Unless given, the output pattern's extension decides.
+            output_compression: output_compression
+                .unwrap_or_else(|| Compression::from_extension(demand.output_pattern())),
+            encoding,
+            dt_start,
+            dt_end,
+            unit,
+            interval,
+            alignment,
+            match_mode,
+            slots,
+            present_only: false,
+            policy,
+            inputs,
+        }
+    }
+
+    /// Drops the slots that are not in `present` and plans the inputs again.
+    /// Present slots start on unit boundaries, so slots are compared by the
+    /// boundary they fall in.
+    pub fn keep_present(&mut self, demand: &BrewingDemand, accept_formats: &[String], present: &BTreeSet<DateTime<Tz>>) {
+        let unit = self.unit;
+        self.slots.retain(|dt| present.contains(&unit.floor(*dt)));
+        self.present_only = true;
+        self.inputs = input_plans(demand, self.brewer, accept_formats, &self.slots, self.unit, self.policy);
+    }
+
+    /// Slots that will be written from the preferred distributions, counting
+    /// filled ones.
+    pub fn total_slots(&self) -> usize {
+        self.inputs
+            .iter()
+            .filter_map(|input| input.candidates.first())
+            .map(|d| match self.policy {
+                OutOfWindowPolicy::Fill => d.slots_inside + d.slots_outside,
+                _ => d.slots_inside,
+            })
+            .sum()
+    }
+
+    /// Fails, listing every rejected distribution, when an input has none
+    /// left to brew from.
+    pub fn check(&self) -> Result<(), String> {
+        let problems: Vec<String> = self
+            .inputs
+            .iter()
+            .filter(|input| input.candidates.is_empty())
+            .map(|input| {
+                let reasons: Vec<String> = input
+                    .rejected
+                    .iter()
+                    .map(|(base_url, reason)| format!("\n    {}: {}", base_url, reason))
+                    .collect();
+                format!("  {}: no usable distribution{}", input.dataset, reasons.concat())
+            })
+            .collect();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} input(s) cannot be brewed for {} .. {}:\n{}",
+                problems.len(),
+                self.dt_start.to_rfc3339(),
+                self.dt_end.to_rfc3339(),
+                problems.join("\n")
+            ))
+        }
+    }
+}
+
+fn input_plans(
+    demand: &BrewingDemand,
+    brewer: BrewerKind,
+    accept_formats: &[String],
+    slots: &[DateTime<Tz>],
+    unit: SlotUnit,
+    policy: OutOfWindowPolicy,
+) -> Vec<InputPlan> {
+    demand
+        .inputs
+        .iter()
+        .map(|input| {
+            let values = placeholders::known_values(demand, input);
+            InputPlan::new(demand, input, values, brewer, accept_formats, slots, unit, policy)
+        })
+        .collect()
+}
+
+fn format_window(d: &DistributionPlan) -> String {
+    format!(
+        "{} .. {}",
+        d.window_start.map_or("(open)".to_string(), |t| t.to_rfc3339()),
+        d.window_end.map_or("(open)".to_string(), |t| t.to_rfc3339())
+    )
+}
+
+impl fmt::Display for BrewPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "requested: {} .. {} in {} ({} slots, {} interval, {} alignment)",
+            self.dt_start.to_rfc3339(),
+            self.dt_end.to_rfc3339(),
+            self.dt_start.timezone().name(),
+            self.unit.as_str(),
+            self.interval.as_str(),
+            self.alignment.as_str()
+        )?;
+        write!(f, "slots: {}", self.slots.len())?;
+        if self.present_only {
+            write!(f, " (present in the inputs)")?;
+        }
+        // Long periods show their first and last slots only.
+        let shown: Vec<(usize, &DateTime<Tz>)> = if self.slots.len() > 2 * SHOWN_SLOTS {
+            self.slots.iter().enumerate().take(SHOWN_SLOTS)
+                .chain(self.slots.iter().enumerate().skip(self.slots.len() - SHOWN_SLOTS))
+                .collect()
+        } else {
+            self.slots.iter().enumerate().collect()
+        };
+        for (i, (n, slot)) in shown.iter().enumerate() {
+            if i > 0 && shown[i - 1].0 + 1 != *n {
+                write!(f, "\n  ...")?;
+            }
+            write!(f, "\n  {}", slot.to_rfc3339())?;
+        }
+        writeln!(f)?;
+        writeln!(f, "out of window: {}", self.policy.as_str())?;
+        writeln!(f, "brewer: {}", self.brewer.as_str())?;
+        writeln!(f, "match mode: {}", self.match_mode.as_str())?;
+        writeln!(f, "output compression: {}", self.output_compression.as_str())?;
+        write!(
+            f,
+            "encoding: input {}, output {}{}",
+            self.encoding.input.map_or("from distribution or detected", |e| e.name()),
+            self.encoding.output.name(),
+            if self.encoding.output_bom { " with BOM" } else { "" }
+        )?;
+        for (i, input) in self.inputs.iter().enumerate() {
+            write!(f, "\n[{}] {}", i, input.dataset)?;
+            if !input.values.is_empty() {
+                let values: Vec<String> = input.values.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                write!(f, "\n  placeholders: {}", values.join(", "))?;
+            }
+            for (rank, d) in input.candidates.iter().enumerate() {
+                let role = if rank == 0 { "use" } else { "fallback" };
+                write!(f, "\n  {} {} ({}", role, d.base_url, d.pattern)?;
+                if let Some(format) = &d.encoding_format {
+                    write!(f, ", {}", format)?;
+                }
+                if self.encoding.input.is_none() {
+                    write!(f, ", {}", d.charset.map_or("detected charset", |e| e.name()))?;
+                }
+                write!(f, ")")?;
+                write!(f, "\n      window:    {}", format_window(d))?;
+                match d.effective {
+                    Some((start, end)) => write!(
+                        f,
+                        "\n      effective: {} .. {} ({} slot(s), {} outside)",
+                        start.to_rfc3339(),
+                        end.to_rfc3339(),
+                        d.slots_inside,
+                        d.slots_outside
+                    )?,
+                    None => write!(f, "\n      effective: (none, {} slot(s) outside)", d.slots_outside)?,
+                }
+            }
+            for (base_url, reason) in &input.rejected {
+                write!(f, "\n  skip {}: {}", base_url, reason.replace('\n', "\n    "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::json_ld_loader::{ParseMode, DEFAULT_PROVENANCE_DEPTH};
+    use crate::ref_resolver::RefResolver;
+
+    async fn demand(output_pattern: &str) -> BrewingDemand {
+        let raw = json!({
+            "dbp:brewerInfo": { "schema:name": "dbpBrewerTemplate" },
+            "dbp:brewerInput": [{
+                "schema:dataset": {
+                    "schema:name": "sample",
+                    "schema:distribution": { "dbp:baseUrl": "file:///tmp/in/", "dbp:pattern": output_pattern },
+                },
+            }],
+            "dbp:brewerOutputStore": { "dbp:baseUrl": "file:///tmp/out/", "dbp:pattern": output_pattern },
+            "dbp:brewingArgument": [],
+            "dbp:timeZone": "Asia/Tokyo",
+        });
+        let Value::Object(raw) = raw else { unreachable!() };
+        let resolver = Arc::new(RefResolver::new(None, None, true));
+        BrewingDemand::from_json_ld(&raw, ParseMode::Lenient, resolver, DEFAULT_PROVENANCE_DEPTH, Tz::UTC)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn at(text: &str) -> DateTime<Tz> {
+        time_parser::parse_time(&Value::from(text), Tz::Asia__Tokyo).unwrap().with_timezone(&Tz::Asia__Tokyo)
+    }
+
+    #[tokio::test]
+    async fn present_slots_match_by_their_boundary() {
+        // Starts off the boundary, given in the zone or with another offset.
+        let cases = [
+            (
+                "%Y/%Y-%m-%d.csv",
+                SlotUnit::Day,
+                "2023-09-01T09:30:00",
+                "2023-09-04T09:30:00",
+                vec!["2023-09-01", "2023-09-03"],
+                vec!["2023-09-01T09:30:00", "2023-09-03T09:30:00"],
+            ),
+            (
+                "%Y/%Y-%m-%d.csv",
+                SlotUnit::Day,
+                "2023-09-01T00:00:00Z",
+                "2023-09-03T00:00:00Z",
+                vec!["2023-09-02"],
+                vec!["2023-09-02T09:00:00"],
+            ),
+            (
+                "%Y/%m/%d/%H.csv",
+                SlotUnit::Hour,
+                "2023-09-01T00:15:00",
+                "2023-09-01T03:15:00",
+                vec!["2023-09-01T01:00:00", "2023-09-01T03:00:00", "2023-09-01T05:00:00"],
+                vec!["2023-09-01T01:15:00", "2023-09-01T03:15:00"],
+            ),
+        ];
+        for (pattern, unit, start, end, present, expected) in cases {
+            let demand = demand(pattern).await;
+            let mut plan = BrewPlan::new(
+                &demand,
+                at(start),
+                at(end),
+                unit,
+                IntervalMode::Closed,
+                Alignment::None,
+                MatchMode::default(),
+                OutOfWindowPolicy::Skip,
+                &[],
+                None,
+                TextEncoding::default(),
+            );
+            let present: BTreeSet<DateTime<Tz>> = present.into_iter().map(at).collect();
+            plan.keep_present(&demand, &[], &present);
+            let expected: Vec<DateTime<Tz>> = expected.into_iter().map(at).collect();
+            assert_eq!(plan.slots, expected, "{} .. {}", start, end);
+            assert!(plan.present_only);
+        }
+    }
+
+    #[tokio::test]
+    async fn windows_exclude_their_end() {
+        let demand = demand("%Y/%Y-%m-%d.csv").await;
+        let mut distribution = demand.inputs[0].distributions[0].clone();
+        distribution.info.start_time = Some(time_parser::to_timestamp(&at("2023-09-02T12:00:00").fixed_offset()));
+        distribution.info.end_time = Some(time_parser::to_timestamp(&at("2023-09-04T00:00:00").fixed_offset()));
+        let slots: Vec<DateTime<Tz>> = ["2023-09-01", "2023-09-02", "2023-09-03", "2023-09-04"].into_iter().map(at).collect();
+        let plan = DistributionPlan::new(&distribution, &slots, SlotUnit::Day);
+        let covered: Vec<bool> = slots.iter().map(|&dt| plan.covers(dt, SlotUnit::Day)).collect();
+        assert_eq!(covered, [false, true, true, false]);
+        assert_eq!((plan.slots_inside, plan.slots_outside), (2, 2));
+        assert_eq!(plan.effective, Some((at("2023-09-02"), at("2023-09-03"))));
+    }
+}
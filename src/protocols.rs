@@ -2,3 +2,6 @@ pub const FILE: &'static str = "file://";
 pub const FTP: &'static str = "ftp://";
 pub const HTTP: &'static str = "http://";
 pub const HTTPS: &'static str = "https://";
+
+/// Protocols input distributions can be read from.
+pub const READABLE: &'static [&'static str] = &[FILE];
@@ -0,0 +1,202 @@
+// Standard Library
+use std::path::Path;
+
+// External Library
+use chrono::{DateTime, Local};
+use chrono_tz::Tz;
+use dbp_schema::dbp_schema::{
+    RealWorldDataBrewerInfo, RealWorldDataBrewingArgument, RealWorldDataset, RealWorldDataStoringInfo,
+};
+use json_ld_utils::{
+    DBP_BASE_URL, DBP_BREWER_INFO, DBP_KEY, DBP_PATTERN, SC_DISTRIBUTION, SC_NAME,
+    SC_VALUE,
+};
+use serde_json::{Map, Value};
+
+use crate::brewing_demand::BrewingDemand;
+use crate::protocols;
+use crate::time_parser;
+
+pub const PROVENANCE_FILE_NAME: &str = "_real_world_dataset.jsonld";
+
+/// Builds a RealWorldDataset describing the brewed output store.
+/// `generatedFrom` points at the demand's input datasets, `generatedUsing` at
+/// the brewer info and `generatedArgs` at the brewing arguments, while the
+/// distribution covers the time range that was actually written.
+pub fn build_output_dataset(
+    demand: &BrewingDemand,
+    output_base_url: &str,
+    output_pattern: &str,
+    brewed_range: Option<(DateTime<Tz>, DateTime<Tz>)>,
+) -> RealWorldDataset {
+    let brewer_name = &demand.brewer_info.name;
+    let name = match demand.raw.get(SC_NAME).and_then(|v| v.as_str()).or(demand.id.as_deref()) {
+        Some(demand_name) => format!("{} output of {}", brewer_name, demand_name),
+        None => format!("{} output", brewer_name),
+    };
+
+    RealWorldDataset {
+        name: Some(name),
+        generated_from: demand
+            .inputs
+            .iter()
+            .map(|input| match &input.dataset.id {
+                Some(id) => RealWorldDataset { id: Some(id.clone()), ..Default::default() },
+                // Anonymous inputs keep their name and first distribution so
+                // the lineage is not lost.
+                None => RealWorldDataset {
+                    name: input.dataset.name.clone(),
+                    distribution: input.distributions.first().map(|d| d.info.clone()),
+                    ..Default::default()
+                },
+            })
+            .collect(),
+        generated_using: Some(RealWorldDataBrewerInfo {
+            id: demand.brewer_info.id.clone(),
+            name: Some(brewer_name.clone()),
+            url: demand
+                .raw
+                .get(DBP_BREWER_INFO)
+                .and_then(|v| v.get("schema:url"))
+                .and_then(|v| v.as_str())
+                .map(String::from),
+        }),
+        generated_args: demand
+            .arguments
+            .iter()
+            .map(|arg| RealWorldDataBrewingArgument {
+                id: None,
+                key: Some(arg.key.clone()),
+                value: Some(arg.value.clone()),
+            })
+            .collect(),
+        distribution: Some(RealWorldDataStoringInfo {
+            base_url: Some(output_base_url.to_string()),
+            pattern: Some(output_pattern.to_string()),
+            start_time: brewed_range.map(|(start, _)| time_parser::to_timestamp(&start.fixed_offset())),
+            end_time: brewed_range.map(|(_, end)| time_parser::to_timestamp(&end.fixed_offset())),
+            ..Default::default()
+        }),
+        date_created: Some(time_parser::to_timestamp(&Local::now().fixed_offset())),
+        ..Default::default()
+    }
+}
+
+/// Serializes the dataset as JSON-LD with the demand's `@context`, using the
+/// keys the demand loader reads back, so the output can be brewed from in
+/// turn.
+pub fn to_json_ld(dataset: &RealWorldDataset, demand: &BrewingDemand) -> Value {
+    let mut json = Map::new();
+    if let Some(context) = demand.raw.get("@context") {
+        json.insert("@context".to_string(), context.clone());
+    }
+    json.insert("@type".to_string(), Value::from("dbp:RealWorldDataset"));
+    json.extend(dataset_json(dataset));
+    if let Some(Value::Object(generated_using)) = json.get_mut("dbp:generatedUsing") {
+        // The brewer version has no schema field but pins down what produced
+        // the output.
+        generated_using.insert("schema:version".to_string(), Value::from(env!("CARGO_PKG_VERSION")));
+    }
+    Value::Object(json)
+}
+
+fn dataset_json(dataset: &RealWorldDataset) -> Map<String, Value> {
+    let mut json = Map::new();
+    insert_str(&mut json, "@id", &dataset.id);
+    insert_str(&mut json, SC_NAME, &dataset.name);
+    insert_str(&mut json, "schema:url", &dataset.url);
+    if !dataset.generated_from.is_empty() {
+        json.insert(
+            "dbp:generatedFrom".to_string(),
+            dataset.generated_from.iter().map(|d| Value::Object(dataset_json(d))).collect(),
+        );
+    }
+    if let Some(brewer) = &dataset.generated_using {
+        let mut using = Map::new();
+        insert_str(&mut using, "@id", &brewer.id);
+        insert_str(&mut using, SC_NAME, &brewer.name);
+        insert_str(&mut using, "schema:url", &brewer.url);
+        json.insert("dbp:generatedUsing".to_string(), Value::Object(using));
+    }
+    if !dataset.generated_args.is_empty() {
+        let args = dataset
+            .generated_args
+            .iter()
+            .map(|arg| {
+                let mut arg_json = Map::new();
+                insert_str(&mut arg_json, "@id", &arg.id);
+                insert_str(&mut arg_json, DBP_KEY, &arg.key);
+                insert_str(&mut arg_json, SC_VALUE, &arg.value);
+                Value::Object(arg_json)
+            })
+            .collect();
+        json.insert("dbp:generatedArgs".to_string(), args);
+    }
+    if let Some(distribution) = &dataset.distribution {
+        json.insert(SC_DISTRIBUTION.to_string(), Value::Object(storing_info_json(distribution)));
+    }
+    insert_time(&mut json, "schema:dateCreated", &dataset.date_created);
+    json
+}
+
+fn storing_info_json(info: &RealWorldDataStoringInfo) -> Map<String, Value> {
+    let mut json = Map::new();
+    insert_str(&mut json, "@id", &info.id);
+    insert_str(&mut json, SC_NAME, &info.name);
+    insert_str(&mut json, "schema:url", &info.url);
+    insert_str(&mut json, DBP_BASE_URL, &info.base_url);
+    insert_str(&mut json, DBP_PATTERN, &info.pattern);
+    insert_time(&mut json, "dbp:startTime", &info.start_time);
+    insert_time(&mut json, "dbp:endTime", &info.end_time);
+    json
+}
+
+fn insert_str(json: &mut Map<String, Value>, key: &str, value: &Option<String>) {
+    if let Some(value) = value {
+        json.insert(key.to_string(), Value::from(value.as_str()));
+    }
+}
+
+fn insert_time(json: &mut Map<String, Value>, key: &str, value: &Option<prost_types::Timestamp>) {
+    if let Some(dt) = value.as_ref().and_then(time_parser::from_timestamp) {
+        json.insert(key.to_string(), Value::from(dt.to_rfc3339()));
+    }
+}
+
+/// Writes the dataset next to the output tree and returns the written path.
+pub fn write_next_to_output(
+    dataset: &Value,
+    output_base_url: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match output_base_url {
+        _ if output_base_url.starts_with(protocols::FILE) => {
+            let output_dir = output_base_url.replace(protocols::FILE, "");
+            std::fs::create_dir_all(&output_dir)?;
+            let path = Path::new(&output_dir).join(PROVENANCE_FILE_NAME);
+            std::fs::write(&path, serde_json::to_string_pretty(dataset)?)?;
+            info!("Wrote output RealWorldDataset to {}", path.display());
+            Ok(Some(path.to_string_lossy().to_string()))
+        }
+        _ => {
+            warn!("Cannot write RealWorldDataset next to non-file output {}", output_base_url);
+            Ok(None)
+        }
+    }
+}
+
+/// POSTs the dataset to the RWDB and returns the `@id` (or `url`) it was
+/// registered under, if the API tells us.
+pub async fn publish(
+    dataset: &Value,
+    publish_url: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let resp = client.post(publish_url).json(dataset).send().await?.error_for_status()?;
+    let created: Value = resp.json().await.unwrap_or(Value::Null);
+    let id = ["@id", "url", "schema:url"]
+        .iter()
+        .find_map(|key| created.get(*key).and_then(|v| v.as_str()))
+        .map(String::from);
+    info!("Published output RealWorldDataset to {} as {:?}", publish_url, id);
+    Ok(id)
+}
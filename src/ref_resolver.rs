@@ -0,0 +1,337 @@
+// Standard Library
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// External Library
+use async_once_cell::OnceCell;
+use async_recursion::async_recursion;
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::catalog::Catalog;
+use crate::json_ld_context;
+
+/// One client for the whole process so that every fetch reuses the same
+/// connection pool.
+pub static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+type FetchResult = Result<Value, String>;
+
+/// A fetch shared by every request for one URL, with when it finished.
+type SharedFetch = Arc<OnceCell<(FetchResult, Instant)>>;
+
+/// How long a fetched document is reused before it is fetched (or
+/// revalidated against the disk cache) again.
+const MEMORY_TTL: Duration = Duration::from_secs(300);
+
+/// Asks servers that negotiate content, such as schema.org, for JSON-LD
+/// rather than their HTML page.
+const ACCEPT_JSON_LD: &str = "application/ld+json, application/json;q=0.9, */*;q=0.1";
+const JSON_LD: &str = "application/ld+json";
+
+/// File name a URL is stored under, in the ref cache and in catalogs.
+pub fn url_file_name(url: &str) -> String {
+    let digest = Sha256::digest(url.as_bytes());
+    let name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}.json", name)
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDocument {
+    url: String,
+    etag: Option<String>,
+    document: Value,
+}
+
+/// Resolves `@ref` URLs to JSON documents. A local catalog, when given, is
+/// consulted first. Fetched documents are reused for `MEMORY_TTL`,
+/// concurrent requests for the same URL wait on the same fetch, and failures
+/// are retried on the next request. Documents are kept on disk keyed by URL
+/// so that later fetches only revalidate them with their ETag. In offline
+/// mode nothing is fetched over the network.
+pub struct RefResolver {
+    cache_dir: Option<PathBuf>,
+    catalog: Option<Catalog>,
+    offline: bool,
+    memory_ttl: Duration,
+    documents: Mutex<HashMap<String, SharedFetch>>,
+}
+
+impl RefResolver {
+    pub fn new(cache_dir: Option<PathBuf>, catalog: Option<Catalog>, offline: bool) -> Self {
+        if let Some(dir) = &cache_dir {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                warn!("Cannot create ref cache directory {}: {}", dir.display(), e);
+            }
+        }
+        RefResolver { cache_dir, catalog, offline, memory_ttl: MEMORY_TTL, documents: Mutex::new(HashMap::new()) }
+    }
+
+    /// Replaces every `{"@ref": url}` object with the referenced document,
+    /// following references inside fetched documents up to `depth` levels.
+    /// References that cannot be resolved are left in place with a warning.
+    /// Sibling references are resolved concurrently.
+    #[async_recursion]
+    pub async fn inline_refs(&self, value: &mut Value, depth: usize) {
+        if let Some(url) = value.get("@ref").and_then(|v| v.as_str()).map(String::from) {
+            if depth == 0 {
+                warn!("Not inlining {}: maximum depth reached", url);
+                return;
+            }
+            match self.fetch(&url).await {
+                Ok(document) => {
+                    *value = document;
+                    self.inline_refs(value, depth - 1).await;
+                }
+                Err(e) => warn!("Not inlining {}: {}", url, e),
+            }
+            return;
+        }
+        match value {
+            Value::Object(obj) => {
+                join_all(obj.values_mut().map(|v| self.inline_refs(v, depth))).await;
+            }
+            Value::Array(values) => {
+                join_all(values.iter_mut().map(|v| self.inline_refs(v, depth))).await;
+            }
+            _ => {}
+        }
+    }
+
+    /// Fetches a document and normalizes its keys against its `@context`.
+    pub async fn fetch(&self, url: &str) -> FetchResult {
+        let document = self.fetch_raw(url).await?;
+        json_ld_context::normalize(&document, self).await
+    }
+
+    /// Fetches a document as published, e.g. a remote `@context`.
+    pub async fn fetch_raw(&self, url: &str) -> FetchResult {
+        let cell = {
+            let mut documents = self.documents.lock().unwrap();
+            let fresh = documents
+                .get(url)
+                .filter(|cell| cell.get().is_none_or(|(_, fetched)| fetched.elapsed() < self.memory_ttl))
+                .cloned();
+            fresh.unwrap_or_else(|| {
+                let cell = Arc::new(OnceCell::new());
+                documents.insert(url.to_string(), cell.clone());
+                cell
+            })
+        };
+        let (result, _) = cell.get_or_init(async { (self.fetch_uncached(url).await, Instant::now()) }).await.clone();
+        if result.is_err() {
+            // Forget the failure, unless a newer fetch has already replaced it.
+            let mut documents = self.documents.lock().unwrap();
+            if documents.get(url).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+                documents.remove(url);
+            }
+        }
+        result
+    }
+
+    async fn fetch_uncached(&self, url: &str) -> FetchResult {
+        if let Some(found) = self.catalog.as_ref().and_then(|catalog| catalog.get(url)) {
+            debug!("ref_url: {} (catalog)", url);
+            return found;
+        }
+        info!("ref_url: {}", url);
+        let cached = self.read_cache(url);
+        if self.offline {
+            return cached
+                .map(|c| c.document)
+                .ok_or_else(|| format!("{} is not in the catalog and network access is disabled", url));
+        }
+
+        let mut req = HTTP_CLIENT.get(url).header(header::ACCEPT, ACCEPT_JSON_LD);
+        if let Some(etag) = cached.as_ref().and_then(|c| c.etag.as_deref()) {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                return match cached {
+                    Some(c) => {
+                        warn!("Fetching {} failed ({}); using cached copy", url, e);
+                        Ok(c.document)
+                    }
+                    None => Err(format!("Fetching {} failed: {}", url, e)),
+                };
+            }
+        };
+
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let Some(c) = cached {
+                debug!("{} not modified; using cached copy", url);
+                return Ok(c.document);
+            }
+        }
+        let mut resp = resp.error_for_status().map_err(|e| format!("Fetching {} failed: {}", url, e))?;
+        let mut etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        if !is_json(resp.headers()) {
+            if let Some(alternate) = alternate_link(resp.headers(), resp.url()) {
+                debug!("{} links to its JSON-LD at {}", url, alternate);
+                resp = HTTP_CLIENT
+                    .get(alternate.clone())
+                    .header(header::ACCEPT, ACCEPT_JSON_LD)
+                    .send()
+                    .await
+                    .and_then(|resp| resp.error_for_status())
+                    .map_err(|e| format!("Fetching {} (linked from {}) failed: {}", alternate, url, e))?;
+                // The ETag belongs to the linked document, so it cannot
+                // revalidate `url` next time.
+                etag = None;
+            }
+        }
+        let body = resp.text().await.map_err(|e| format!("Reading {} failed: {}", url, e))?;
+        let document = serde_json::from_str::<Value>(&body).map_err(|e| format!("{} is not JSON: {}", url, e))?;
+        self.write_cache(&CachedDocument { url: url.to_string(), etag, document: document.clone() });
+        Ok(document)
+    }
+
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        self.cache_dir.as_ref().map(|dir| dir.join(url_file_name(url)))
+    }
+
+    fn read_cache(&self, url: &str) -> Option<CachedDocument> {
+        let text = std::fs::read_to_string(self.cache_path(url)?).ok()?;
+        serde_json::from_str::<CachedDocument>(&text).ok().filter(|c| c.url == url)
+    }
+
+    fn write_cache(&self, cached: &CachedDocument) {
+        if let Some(path) = self.cache_path(&cached.url) {
+            let written = serde_json::to_string(cached)
+                .map_err(|e| e.to_string())
+                .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                warn!("Cannot write ref cache {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn is_json(headers: &header::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+        .unwrap_or(false)
+}
+
+/// The target of a `Link: <...>; rel="alternate"; type="application/ld+json"`
+/// header, resolved against the response URL, as JSON-LD processors follow
+/// for documents served as HTML.
+fn alternate_link(headers: &header::HeaderMap, base: &reqwest::Url) -> Option<reqwest::Url> {
+    headers
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|link| {
+            let (target, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+            let mut rel_alternate = false;
+            let mut json_ld = false;
+            for param in params.split(';') {
+                let Some((name, value)) = param.split_once('=') else { continue };
+                let value = value.trim().trim_matches('"');
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "rel" => rel_alternate = value.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("alternate")),
+                    "type" => json_ld = value.eq_ignore_ascii_case(JSON_LD),
+                    _ => {}
+                }
+            }
+            if rel_alternate && json_ld {
+                base.join(target).ok()
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn resolver() -> RefResolver {
+        RefResolver::new(None, None, false)
+    }
+
+    async fn serve(server: &MockServer, route: &str, document: Value, times: u64) {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_json(document))
+            .expect(times)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn reuses_documents_until_they_expire() {
+        let server = MockServer::start().await;
+        serve(&server, "/doc", json!({ "n": 1 }), 2).await;
+        let url = format!("{}/doc", server.uri());
+
+        let mut resolver = resolver();
+        assert_eq!(resolver.fetch_raw(&url).await, Ok(json!({ "n": 1 })));
+        assert_eq!(resolver.fetch_raw(&url).await, Ok(json!({ "n": 1 })));
+        resolver.memory_ttl = Duration::ZERO;
+        assert_eq!(resolver.fetch_raw(&url).await, Ok(json!({ "n": 1 })));
+    }
+
+    #[tokio::test]
+    async fn retries_after_a_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/doc"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        serve(&server, "/doc", json!({ "n": 1 }), 1).await;
+        let url = format!("{}/doc", server.uri());
+
+        let resolver = resolver();
+        assert!(resolver.fetch_raw(&url).await.is_err());
+        assert_eq!(resolver.fetch_raw(&url).await, Ok(json!({ "n": 1 })));
+    }
+
+    #[tokio::test]
+    async fn inlines_sibling_refs_concurrently() {
+        let server = MockServer::start().await;
+        let delay = Duration::from_millis(400);
+        for (route, n) in [("/a", 1), ("/b", 2), ("/c", 3)] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "n": n })).set_delay(delay))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let mut document = json!({
+            "a": { "@ref": format!("{}/a", server.uri()) },
+            "list": [{ "@ref": format!("{}/b", server.uri()) }, { "@ref": format!("{}/c", server.uri()) }],
+        });
+
+        let started = Instant::now();
+        resolver().inline_refs(&mut document, 2).await;
+        assert_eq!(document, json!({ "a": { "n": 1 }, "list": [{ "n": 2 }, { "n": 3 }] }));
+        assert!(started.elapsed() < delay * 2, "took {:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,282 @@
+// External Library
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+
+/// Whether `dbp:timePeriodEnd` itself is brewed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum IntervalMode {
+    /// `[start, end]`: a slot starting exactly at the end is brewed
+    #[default]
+    Closed,
+    /// `[start, end)`: the end is exclusive
+    #[value(name = "half_open")]
+    HalfOpen,
+}
+
+impl IntervalMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntervalMode::Closed => "closed",
+            IntervalMode::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// How a start that is not on a pattern boundary is aligned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Alignment {
+    /// Step from the start as given
+    #[default]
+    None,
+    /// Move the start back to the boundary of the slot containing it
+    Floor,
+    /// Move the start forward to the next boundary
+    Ceil,
+}
+
+impl Alignment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Alignment::None => "none",
+            Alignment::Floor => "floor",
+            Alignment::Ceil => "ceil",
+        }
+    }
+}
+
+/// The smallest time unit in a path pattern, which is the size of a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SlotUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+impl SlotUnit {
+    pub fn from_placeholder(placeholder: &str) -> Option<Self> {
+        match placeholder {
+            "Y" => Some(SlotUnit::Year),
+            "m" => Some(SlotUnit::Month),
+            "d" => Some(SlotUnit::Day),
+            "H" => Some(SlotUnit::Hour),
+            "M" => Some(SlotUnit::Minute),
+            "S" => Some(SlotUnit::Second),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlotUnit::Second => "second",
+            SlotUnit::Minute => "minute",
+            SlotUnit::Hour => "hour",
+            SlotUnit::Day => "day",
+            SlotUnit::Month => "month",
+            SlotUnit::Year => "year",
+        }
+    }
+
+    /// Start of the slot containing `dt`, in `dt`'s zone. Hours and shorter
+    /// units are floored by subtracting the time elapsed since the boundary,
+    /// so that the repeated hour of a DST overlap keeps its own offset.
+    pub fn floor(&self, dt: DateTime<Tz>) -> DateTime<Tz> {
+        let local = dt.naive_local();
+        let date = local.date();
+        let nanos = Duration::nanoseconds(local.nanosecond() as i64);
+        let floored = match self {
+            SlotUnit::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).map(midnight),
+            SlotUnit::Month => NaiveDate::from_ymd_opt(date.year(), date.month(), 1).map(midnight),
+            SlotUnit::Day => Some(midnight(date)),
+            SlotUnit::Hour => return dt - Duration::seconds((local.minute() * 60 + local.second()) as i64) - nanos,
+            SlotUnit::Minute => return dt - Duration::seconds(local.second() as i64) - nanos,
+            SlotUnit::Second => return dt - nanos,
+        };
+        floored.map_or(dt, |naive| in_zone(&dt.timezone(), naive))
+    }
+
+    /// `dt` moved by `n` units. Years, months and days follow the calendar
+    /// (and daylight saving) of the zone; shorter units are exact durations.
+    pub fn add(&self, dt: DateTime<Tz>, n: u32) -> DateTime<Tz> {
+        let local = dt.naive_local();
+        let tz = dt.timezone();
+        match self {
+            SlotUnit::Year => local.checked_add_months(Months::new(12 * n)).map_or(dt, |t| in_zone(&tz, t)),
+            SlotUnit::Month => local.checked_add_months(Months::new(n)).map_or(dt, |t| in_zone(&tz, t)),
+            SlotUnit::Day => in_zone(&tz, local + Duration::days(n as i64)),
+            SlotUnit::Hour => dt + Duration::hours(n as i64),
+            SlotUnit::Minute => dt + Duration::minutes(n as i64),
+            SlotUnit::Second => dt + Duration::seconds(n as i64),
+        }
+    }
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default()
+}
+
+/// Resolves a wall-clock time, taking the earlier instant when it is
+/// ambiguous and skipping forward when it falls into a DST gap.
+pub fn in_zone(tz: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .unwrap_or_else(|| tz.from_utc_datetime(&local))
+}
+
+/// The start of every slot of `unit` in the requested period.
+pub fn slots(
+    dt_start: DateTime<Tz>,
+    dt_end: DateTime<Tz>,
+    unit: SlotUnit,
+    interval: IntervalMode,
+    alignment: Alignment,
+) -> Vec<DateTime<Tz>> {
+    let first = match alignment {
+        Alignment::None => dt_start,
+        Alignment::Floor => unit.floor(dt_start),
+        Alignment::Ceil => {
+            let floor = unit.floor(dt_start);
+            if floor == dt_start { floor } else { unit.add(floor, 1) }
+        }
+    };
+    let mut slots = Vec::new();
+    // Always step from the first slot so that month ends do not drift.
+    let mut n = 0;
+    loop {
+        let dt = unit.add(first, n);
+        let inside = match interval {
+            IntervalMode::Closed => dt <= dt_end,
+            IntervalMode::HalfOpen => dt < dt_end,
+        };
+        if !inside || (n > 0 && dt == unit.add(first, n - 1)) {
+            break;
+        }
+        slots.push(dt);
+        n += 1;
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(tz: Tz, y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Tz> {
+        tz.with_ymd_and_hms(y, mo, d, h, mi, 0).earliest().unwrap()
+    }
+
+    fn rfc3339(slots: Vec<DateTime<Tz>>) -> Vec<String> {
+        slots.iter().map(|dt| dt.to_rfc3339()).collect()
+    }
+
+    #[test]
+    fn months_step_from_the_first_slot_without_drifting() {
+        let tz = Tz::Asia__Tokyo;
+        assert_eq!(SlotUnit::Month.add(local(tz, 2024, 1, 31, 0, 0), 1), local(tz, 2024, 2, 29, 0, 0));
+        assert_eq!(SlotUnit::Month.add(local(tz, 2023, 1, 31, 0, 0), 1), local(tz, 2023, 2, 28, 0, 0));
+        assert_eq!(SlotUnit::Year.add(local(tz, 2024, 2, 29, 0, 0), 1), local(tz, 2025, 2, 28, 0, 0));
+        let slots = slots(
+            local(tz, 2024, 1, 31, 0, 0),
+            local(tz, 2024, 5, 31, 0, 0),
+            SlotUnit::Month,
+            IntervalMode::Closed,
+            Alignment::None,
+        );
+        assert_eq!(
+            rfc3339(slots),
+            [
+                "2024-01-31T00:00:00+09:00",
+                "2024-02-29T00:00:00+09:00",
+                "2024-03-31T00:00:00+09:00",
+                "2024-04-30T00:00:00+09:00",
+                "2024-05-31T00:00:00+09:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_across_dst_gaps_and_overlaps() {
+        let tz = Tz::America__New_York;
+        let cases = [
+            // Spring forward: 02:00 does not exist.
+            (
+                local(tz, 2024, 3, 10, 0, 0),
+                local(tz, 2024, 3, 10, 4, 0),
+                SlotUnit::Hour,
+                vec!["2024-03-10T00:00:00-05:00", "2024-03-10T01:00:00-05:00", "2024-03-10T03:00:00-04:00", "2024-03-10T04:00:00-04:00"],
+            ),
+            // Fall back: 01:00 comes twice.
+            (
+                local(tz, 2024, 11, 3, 0, 0),
+                local(tz, 2024, 11, 3, 2, 0),
+                SlotUnit::Hour,
+                vec![
+                    "2024-11-03T00:00:00-04:00",
+                    "2024-11-03T01:00:00-04:00",
+                    "2024-11-03T01:00:00-05:00",
+                    "2024-11-03T02:00:00-05:00",
+                ],
+            ),
+            // Days keep the wall-clock time; a time in the gap moves forward.
+            (
+                local(tz, 2024, 3, 9, 2, 30),
+                local(tz, 2024, 3, 11, 2, 30),
+                SlotUnit::Day,
+                vec!["2024-03-09T02:30:00-05:00", "2024-03-10T03:30:00-04:00", "2024-03-11T02:30:00-04:00"],
+            ),
+            // An ambiguous time is read as the earlier instant.
+            (
+                local(tz, 2024, 11, 2, 1, 30),
+                local(tz, 2024, 11, 4, 1, 30),
+                SlotUnit::Day,
+                vec!["2024-11-02T01:30:00-04:00", "2024-11-03T01:30:00-04:00", "2024-11-04T01:30:00-05:00"],
+            ),
+        ];
+        for (start, end, unit, expected) in cases {
+            assert_eq!(rfc3339(slots(start, end, unit, IntervalMode::Closed, Alignment::None)), expected, "{} {:?}", start, unit);
+        }
+    }
+
+    #[test]
+    fn floors_to_the_boundary_in_the_zone() {
+        let new_york = Tz::America__New_York;
+        // 01:30 EST, in the repeated hour.
+        let repeated = new_york.from_utc_datetime(&NaiveDate::from_ymd_opt(2024, 11, 3).unwrap().and_hms_opt(6, 30, 0).unwrap());
+        let cases = [
+            (SlotUnit::Hour, repeated, "2024-11-03T01:00:00-05:00"),
+            (SlotUnit::Minute, repeated + Duration::seconds(59), "2024-11-03T01:30:00-05:00"),
+            (SlotUnit::Day, repeated, "2024-11-03T00:00:00-04:00"),
+            (SlotUnit::Month, repeated, "2024-11-01T00:00:00-04:00"),
+            (SlotUnit::Year, repeated, "2024-01-01T00:00:00-05:00"),
+            // Midnight was skipped in São Paulo that day.
+            (SlotUnit::Day, local(Tz::America__Sao_Paulo, 2018, 11, 4, 12, 0), "2018-11-04T01:00:00-02:00"),
+            (SlotUnit::Hour, local(Tz::Asia__Kolkata, 2024, 1, 1, 10, 45), "2024-01-01T10:00:00+05:30"),
+        ];
+        for (unit, dt, expected) in cases {
+            assert_eq!(unit.floor(dt).to_rfc3339(), expected, "{:?} {}", unit, dt);
+        }
+    }
+
+    #[test]
+    fn aligns_the_start_as_asked() {
+        let tz = Tz::Asia__Tokyo;
+        let end = local(tz, 2024, 1, 1, 12, 0);
+        let cases = [
+            (10, 20, Alignment::None, IntervalMode::Closed, vec!["10:20", "11:20"]),
+            (10, 20, Alignment::Floor, IntervalMode::Closed, vec!["10:00", "11:00", "12:00"]),
+            (10, 20, Alignment::Ceil, IntervalMode::Closed, vec!["11:00", "12:00"]),
+            (10, 20, Alignment::Ceil, IntervalMode::HalfOpen, vec!["11:00"]),
+            (10, 0, Alignment::Ceil, IntervalMode::Closed, vec!["10:00", "11:00", "12:00"]),
+            (12, 0, Alignment::None, IntervalMode::HalfOpen, vec![]),
+        ];
+        for (hour, minute, alignment, interval, expected) in cases {
+            let slots = slots(local(tz, 2024, 1, 1, hour, minute), end, SlotUnit::Hour, interval, alignment);
+            let times: Vec<String> = slots.iter().map(|dt| dt.format("%H:%M").to_string()).collect();
+            assert_eq!(times, expected, "{}:{} {:?} {:?}", hour, minute, alignment, interval);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+// Standard Library
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// External Library
+use chrono::Local;
+use serde_json::{json, Map, Value};
+
+pub const DBP_BREWING_STATUS: &str = "dbp:brewingStatus";
+pub const DBP_BREWING_PROGRESS: &str = "dbp:brewingProgress";
+pub const DBP_BREWING_ERROR: &str = "dbp:brewingError";
+pub const DBP_BREWER_OUTPUT: &str = "dbp:brewerOutput";
+pub const DBP_DEMAND: &str = "dbp:demand";
+
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DemandState {
+    Pending,
+    Accepted,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl DemandState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DemandState::Pending => "pending",
+            DemandState::Accepted => "accepted",
+            DemandState::Running => "running",
+            DemandState::Succeeded => "succeeded",
+            DemandState::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReportOptions {
+    /// PATCH state transitions to the demand's own URL.
+    pub report_status: bool,
+    /// POST state transitions to this URL instead of the demand URL.
+    pub callback_url: Option<String>,
+    /// The demand was already marked accepted when a worker claimed it.
+    pub accepted: bool,
+}
+
+enum ReportTarget {
+    Demand(String),
+    Callback { url: String, demand_url: Option<String> },
+}
+
+pub struct StatusReporter {
+    client: reqwest::Client,
+    target: Option<ReportTarget>,
+    last_progress: Mutex<Option<Instant>>,
+}
+
+impl StatusReporter {
+    pub fn new(options: &ReportOptions, demand_url: Option<String>) -> Self {
+        let target = match (&options.callback_url, demand_url) {
+            (Some(url), demand_url) => Some(ReportTarget::Callback { url: url.clone(), demand_url }),
+            (None, Some(demand_url)) if options.report_status => Some(ReportTarget::Demand(demand_url)),
+            (None, None) if options.report_status => {
+                warn!("Status reporting requested but the demand has no URL to report to");
+                None
+            }
+            _ => None,
+        };
+        StatusReporter {
+            client: reqwest::Client::new(),
+            target,
+            last_progress: Mutex::new(None),
+        }
+    }
+
+    pub async fn report(
+        &self,
+        state: DemandState,
+        progress: &Progress,
+        error: Option<&str>,
+        output: Option<&str>,
+    ) {
+        info!(
+            "Demand state: {} ({}/{} done, {} failed)",
+            state.as_str(), progress.done, progress.total, progress.failed
+        );
+        let target = match &self.target {
+            Some(target) => target,
+            None => return,
+        };
+
+        let mut body = Map::new();
+        body.insert(DBP_BREWING_STATUS.to_string(), Value::from(state.as_str()));
+        body.insert(
+            DBP_BREWING_PROGRESS.to_string(),
+            json!({ "total": progress.total, "done": progress.done, "failed": progress.failed }),
+        );
+        if let Some(error) = error {
+            body.insert(DBP_BREWING_ERROR.to_string(), Value::from(error));
+        }
+        if let Some(output) = output {
+            body.insert(DBP_BREWER_OUTPUT.to_string(), json!({ "@id": output }));
+        }
+        body.insert("schema:dateModified".to_string(), Value::from(Local::now().to_rfc3339()));
+
+        let req = match target {
+            ReportTarget::Demand(url) => self.client.patch(url),
+            ReportTarget::Callback { url, demand_url } => {
+                if let Some(demand_url) = demand_url {
+                    body.insert(DBP_DEMAND.to_string(), json!({ "@id": demand_url }));
+                }
+                self.client.post(url)
+            }
+        };
+        match req.json(&body).send().await.and_then(|r| r.error_for_status()) {
+            Ok(_) => debug!("Reported demand state {}", state.as_str()),
+            Err(e) => warn!("Failed to report demand state {}: {}", state.as_str(), e),
+        }
+    }
+
+    /// Reports `Running` with the current counts, at most once per
+    /// `PROGRESS_REPORT_INTERVAL` so that fine-grained patterns do not flood the API.
+    pub async fn report_progress(&self, progress: &Progress) {
+        {
+            let mut last = self.last_progress.lock().unwrap();
+            if let Some(at) = *last {
+                if at.elapsed() < PROGRESS_REPORT_INTERVAL && progress.done < progress.total {
+                    return;
+                }
+            }
+            *last = Some(Instant::now());
+        }
+        self.report(DemandState::Running, progress, None, None).await;
+    }
+}
+
+pub fn demand_url(demand: &Value) -> Option<String> {
+    ["@id", "schema:url"]
+        .iter()
+        .filter_map(|key| demand.get(*key).and_then(|v| v.as_str()))
+        .find(|url| url.starts_with("http"))
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn options(report_status: bool, callback_url: Option<String>) -> ReportOptions {
+        ReportOptions { report_status, callback_url, accepted: false }
+    }
+
+    #[tokio::test]
+    async fn patches_the_demand() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/demands/1/"))
+            .and(body_partial_json(json!({
+                DBP_BREWING_STATUS: "failed",
+                DBP_BREWING_PROGRESS: { "total": 3, "done": 1, "failed": 1 },
+                DBP_BREWING_ERROR: "broken",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let reporter = StatusReporter::new(&options(true, None), Some(format!("{}/demands/1/", server.uri())));
+        let progress = Progress { total: 3, done: 1, failed: 1 };
+        reporter.report(DemandState::Failed, &progress, Some("broken"), None).await;
+    }
+
+    #[tokio::test]
+    async fn posts_to_the_callback_with_the_demand() {
+        let server = MockServer::start().await;
+        let demand = "https://rwdb.example/demands/1/";
+        Mock::given(method("POST"))
+            .and(path("/callback"))
+            .and(body_partial_json(json!({
+                DBP_BREWING_STATUS: "succeeded",
+                DBP_BREWER_OUTPUT: { "@id": "file:///out/" },
+                DBP_DEMAND: { "@id": demand },
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        // The callback wins over PATCHing the demand.
+        let reporter = StatusReporter::new(&options(true, Some(format!("{}/callback", server.uri()))), Some(demand.to_string()));
+        reporter.report(DemandState::Succeeded, &Progress::default(), None, Some("file:///out/")).await;
+    }
+
+    #[tokio::test]
+    async fn reports_nothing_unless_asked() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        let reporter = StatusReporter::new(&options(false, None), Some(format!("{}/demands/1/", server.uri())));
+        reporter.report(DemandState::Running, &Progress::default(), None, None).await;
+    }
+
+    #[tokio::test]
+    async fn throttles_progress_but_reports_the_last_slot() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(body_partial_json(json!({ DBP_BREWING_STATUS: "running", DBP_BREWING_PROGRESS: { "done": 1 } })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(body_partial_json(json!({ DBP_BREWING_STATUS: "running", DBP_BREWING_PROGRESS: { "done": 4 } })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Slots 2 and 3 come within the interval.
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        let reporter = StatusReporter::new(&options(true, None), Some(format!("{}/demands/1/", server.uri())));
+        for done in 1..=4 {
+            reporter.report_progress(&Progress { total: 4, done, failed: 0 }).await;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Standard Library
+use std::io::{Cursor, Read};
+
+// External Library
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+/// How many leading bytes of an input are looked at to guess its encoding.
+const DETECTION_BYTES: usize = 64 * 1024;
+
+/// Character encodings of brewer inputs and outputs.
+#[derive(Clone, Copy, Debug)]
+pub struct TextEncoding {
+    /// Overrides the charset of every input; `None` takes it from the
+    /// distribution or structure info, or detects it.
+    pub input: Option<&'static Encoding>,
+    pub output: &'static Encoding,
+    /// Start UTF-8 and UTF-16 outputs with a byte order mark.
+    pub output_bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        TextEncoding { input: None, output: UTF_8, output_bom: false }
+    }
+}
+
+/// Looks up an encoding by any of its WHATWG labels (`Shift_JIS`, `sjis`,
+/// `EUC-JP`, `utf-8`, ...).
+pub fn lookup(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| format!("unknown character encoding {:?}", label))
+}
+
+/// The media type of an `encodingFormat`, without parameters.
+pub fn media_type(encoding_format: &str) -> &str {
+    encoding_format.split(';').next().unwrap_or_default().trim()
+}
+
+/// The `charset` parameter of an `encodingFormat` such as
+/// `text/csv; charset=Shift_JIS`.
+pub fn charset(encoding_format: &str) -> Option<&str> {
+    encoding_format.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Guesses the encoding of the leading bytes of an input: a byte order
+/// mark, then valid UTF-8, then a statistical guess.
+fn detect(head: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return encoding;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => return UTF_8,
+        // Cut off in the middle of a character by the detection window.
+        Err(e) if e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(head, head.len() < DETECTION_BYTES);
+    detector.guess(None, true)
+}
+
+/// Wraps an input so that it reads as UTF-8, decoding from `encoding` or,
+/// when that is `None`, from the detected one. A byte order mark is always
+/// honoured and removed.
+pub fn decode(mut reader: Box<dyn Read>, encoding: Option<&'static Encoding>, name: &str) -> std::io::Result<Box<dyn Read>> {
+    // Decompressors return short reads, so a single read may see only a
+    // few bytes; fill the whole window (or reach the end) before guessing.
+    let mut head = Vec::with_capacity(DETECTION_BYTES);
+    reader.by_ref().take(DETECTION_BYTES as u64).read_to_end(&mut head)?;
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => {
+            let encoding = detect(&head);
+            debug!("Detected {} as {}", name, encoding.name());
+            encoding
+        }
+    };
+    let has_bom = Encoding::for_bom(&head).is_some();
+    let reader = Cursor::new(head).chain(reader);
+    if encoding == UTF_8 && !has_bom {
+        return Ok(Box::new(reader));
+    }
+    Ok(Box::new(DecodeReaderBytesBuilder::new().encoding(Some(encoding)).build(reader)))
+}
+
+/// Encodes brewed text for writing. Characters the output encoding cannot
+/// represent are written as numeric character references.
+pub fn encode(text: &str, encoding: &TextEncoding) -> Vec<u8> {
+    let output = encoding.output;
+    let bom = encoding.output_bom;
+    if output == UTF_16LE || output == UTF_16BE {
+        let mut bytes = Vec::with_capacity(2 * text.len() + 2);
+        let units = bom.then_some(0xfeff_u16).into_iter().chain(text.encode_utf16());
+        for unit in units {
+            if output == UTF_16LE {
+                bytes.extend(unit.to_le_bytes());
+            } else {
+                bytes.extend(unit.to_be_bytes());
+            }
+        }
+        return bytes;
+    }
+    let (encoded, _, had_errors) = output.encode(text);
+    if had_errors {
+        warn!("Some characters cannot be represented in {} and were written as character references", output.name());
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() + 3);
+    if bom && output == UTF_8 {
+        bytes.extend([0xef, 0xbb, 0xbf]);
+    }
+    bytes.extend_from_slice(&encoded);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::SHIFT_JIS;
+
+    /// Hands out one byte per read, as a decompressor may.
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn decoded(bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> String {
+        let mut text = String::new();
+        decode(Box::new(Trickle(Cursor::new(bytes))), encoding, "test").unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn detects_across_short_reads() {
+        let text = "時刻,観測所,気温\n2023-09-01T00:00:00,東京,25.1\n".repeat(20);
+        let (shift_jis, _, _) = SHIFT_JIS.encode(&text);
+        assert_eq!(decoded(shift_jis.into_owned(), None), text);
+        assert_eq!(decoded(text.clone().into_bytes(), None), text);
+        let mut with_bom = vec![0xef, 0xbb, 0xbf];
+        with_bom.extend(text.as_bytes());
+        assert_eq!(decoded(with_bom, None), text);
+    }
+
+    #[test]
+    fn reads_past_the_detection_window() {
+        let text = "a".repeat(DETECTION_BYTES) + "終わり";
+        let (shift_jis, _, _) = SHIFT_JIS.encode(&text);
+        assert_eq!(decoded(shift_jis.into_owned(), Some(SHIFT_JIS)), text);
+        assert_eq!(decoded(text.clone().into_bytes(), None), text);
+    }
+}
@@ -0,0 +1,128 @@
+// External Library
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+const XSD_DATE_TIME: [&str; 2] = ["xsd:dateTime", "http://www.w3.org/2001/XMLSchema#dateTime"];
+const XSD_DATE: [&str; 2] = ["xsd:date", "http://www.w3.org/2001/XMLSchema#date"];
+
+/// Parses any of the time forms found in RWDB JSON-LD:
+/// - ISO 8601 / RFC 3339 text (`2023-09-01T00:00:00+09:00`, `2023-09-01T00:00:00Z`,
+///   `2023-09-01T00:00:00+0900`, `2023-09-01 00:00:00`, `2023-09-01`)
+/// - epoch seconds as a number (fractions allowed)
+/// - typed literals (`{"@value": "...", "@type": "xsd:dateTime"}`)
+/// - protobuf `Timestamp` objects (`{"seconds": ..., "nanos": ...}`)
+///
+/// Text without an offset is local time in `tz`. A local time that occurs
+/// twice because clocks were set back is read as the earlier instant; one
+/// skipped because clocks were set forward is an error.
+pub fn parse_time(value: &Value, tz: Tz) -> Result<DateTime<FixedOffset>, String> {
+    match value {
+        Value::String(text) => parse_time_str(text, tz),
+        Value::Number(n) => {
+            let epoch = n.as_f64().ok_or_else(|| format!("invalid epoch seconds {}", n))?;
+            let seconds = epoch.floor();
+            let nanos = ((epoch - seconds) * 1e9).round() as u32;
+            from_epoch(seconds as i64, nanos).ok_or_else(|| format!("epoch seconds {} out of range", n))
+        }
+        Value::Object(obj) if obj.contains_key("@value") => {
+            match obj.get("@type").and_then(|v| v.as_str()) {
+                None => {}
+                Some(t) if XSD_DATE_TIME.contains(&t) || XSD_DATE.contains(&t) => {}
+                Some(t) => return Err(format!("unsupported literal type {}", t)),
+            }
+            parse_time(&obj["@value"], tz)
+        }
+        Value::Object(obj) if obj.contains_key("seconds") => {
+            let seconds = integer(&obj["seconds"]).ok_or("seconds must be an integer")?;
+            let nanos = match obj.get("nanos") {
+                None | Some(Value::Null) => 0,
+                Some(v) => integer(v).ok_or("nanos must be an integer")?,
+            };
+            u32::try_from(nanos)
+                .ok()
+                .and_then(|nanos| from_epoch(seconds, nanos))
+                .ok_or_else(|| format!("timestamp {}s {}ns out of range", seconds, nanos))
+        }
+        v => Err(format!("expected a date-time, found {}", v)),
+    }
+}
+
+fn parse_time_str(text: &str, tz: Tz) -> Result<DateTime<FixedOffset>, String> {
+    let text = text.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Ok(dt);
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z", "%Y-%m-%dT%H:%M%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(text, format) {
+            return Ok(dt);
+        }
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return in_time_zone(&naive, tz);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return in_time_zone(&date.and_hms_opt(0, 0, 0).unwrap_or_default(), tz);
+    }
+    Err(format!("invalid ISO 8601 date-time {:?}", text))
+}
+
+fn in_time_zone(naive: &NaiveDateTime, tz: Tz) -> Result<DateTime<FixedOffset>, String> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(dt) => Ok(dt.fixed_offset()),
+        LocalResult::Ambiguous(earlier, later) => {
+            debug!(
+                "{} is ambiguous in {} ({} or {}); using the earlier",
+                naive, tz, earlier.to_rfc3339(), later.to_rfc3339()
+            );
+            Ok(earlier.fixed_offset())
+        }
+        LocalResult::None => Err(format!("{} does not exist in {} (skipped by a daylight saving change)", naive, tz)),
+    }
+}
+
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn from_epoch(seconds: i64, nanos: u32) -> Option<DateTime<FixedOffset>> {
+    DateTime::<Utc>::from_timestamp(seconds, nanos).map(|dt| dt.fixed_offset())
+}
+
+pub fn to_timestamp(dt: &DateTime<FixedOffset>) -> prost_types::Timestamp {
+    prost_types::Timestamp { seconds: dt.timestamp(), nanos: dt.timestamp_subsec_nanos() as i32 }
+}
+
+pub fn from_timestamp(ts: &prost_types::Timestamp) -> Option<DateTime<FixedOffset>> {
+    u32::try_from(ts.nanos).ok().and_then(|nanos| from_epoch(ts.seconds, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn naive_times_are_local_to_the_zone() {
+        let cases = [
+            ("2023-09-01T00:00:00", Tz::UTC, Some("2023-09-01T00:00:00+00:00")),
+            ("2023-09-01T09:30", Tz::Asia__Tokyo, Some("2023-09-01T09:30:00+09:00")),
+            ("2023-09-01", Tz::Asia__Tokyo, Some("2023-09-01T00:00:00+09:00")),
+            // Offsets win over the zone.
+            ("2023-09-01T00:00:00Z", Tz::Asia__Tokyo, Some("2023-09-01T00:00:00+00:00")),
+            // Repeated when clocks go back: the earlier instant.
+            ("2023-11-05T01:30:00", Tz::America__New_York, Some("2023-11-05T01:30:00-04:00")),
+            // Skipped when clocks go forward.
+            ("2023-03-12T02:30:00", Tz::America__New_York, None),
+        ];
+        for (text, tz, expected) in cases {
+            let parsed = parse_time(&Value::from(text), tz).ok().map(|dt| dt.to_rfc3339());
+            assert_eq!(parsed.as_deref(), expected, "{} in {}", text, tz);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+// Standard Library
+use std::collections::HashSet;
+use std::time::Duration;
+
+// External Library
+use json_ld_utils::{DBP_BREWER_INFO, SC_NAME};
+use reqwest::{header, StatusCode};
+use serde_json::{json, Value};
+
+use crate::json_ld_context;
+use crate::ref_resolver::RefResolver;
+use crate::status_reporter::{demand_url, DemandState, DBP_BREWING_STATUS};
+use crate::DemandOptions;
+
+pub struct WorkerConfig {
+    pub demand_list_url: String,
+    pub poll_interval: Duration,
+    pub max_backoff: Duration,
+    pub once: bool,
+    pub demand_options: DemandOptions,
+}
+
+/// Polls the demand list endpoint, claims every pending demand addressed to
+/// the configured brewer name and runs it through `process_demand`. State
+/// transitions are always PATCHed back to the demand (or to the configured
+/// callback) so that other workers and the RWDB see the outcome.
+pub async fn run(config: &WorkerConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    // Shared by every demand brewed here, so documents they have in common
+    // are fetched and cached once.
+    let resolver = crate::demand_resolver(&config.demand_options)?;
+    let brewer_name = &config.demand_options.brewer_name;
+    let mut handled: HashSet<String> = HashSet::new();
+    let mut wait = config.poll_interval;
+    let mut demand_options = config.demand_options.clone();
+    demand_options.report.report_status = true;
+    // The claim already marked every demand brewed here as accepted.
+    demand_options.report.accepted = true;
+
+    loop {
+        match fetch_demands(&client, &config.demand_list_url).await {
+            Ok(demands) => {
+                wait = config.poll_interval;
+                // Read brewer names and states the same way however the RWDB
+                // spelled the keys.
+                let mut normalized = Vec::with_capacity(demands.len());
+                for demand in demands {
+                    match json_ld_context::normalize(&demand, &resolver).await {
+                        Ok(demand) => normalized.push(demand),
+                        Err(e) => {
+                            warn!("Cannot process @context of demand {:?}: {}", demand_url(&demand), e);
+                            normalized.push(demand);
+                        }
+                    }
+                }
+                let demands = normalized;
+                let pending: Vec<&Value> = demands
+                    .iter()
+                    .filter(|d| is_addressed_to(d, brewer_name) && is_pending(d))
+                    .collect();
+                info!("{} pending demand(s) for {}", pending.len(), brewer_name);
+
+                for demand in pending {
+                    let demand_url = match demand_url(demand) {
+                        Some(url) => url,
+                        None => {
+                            warn!("Skipping demand without @id or schema:url: {:?}", demand);
+                            continue;
+                        }
+                    };
+                    if handled.contains(&demand_url) {
+                        continue;
+                    }
+                    let claimed = match claim_demand(&client, &resolver, &demand_url).await {
+                        Ok(Some(claimed)) => {
+                            info!("Claimed demand {}", demand_url);
+                            claimed
+                        }
+                        Ok(None) => {
+                            info!("Demand {} was claimed by another worker", demand_url);
+                            handled.insert(demand_url);
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to claim demand {}: {}", demand_url, e);
+                            continue;
+                        }
+                    };
+                    handled.insert(demand_url.clone());
+
+                    // Brew the copy the claim was made against, not the list item.
+                    match crate::process_demand(claimed.to_string().as_str(), &demand_options, &resolver).await {
+                        Ok(_) => info!("Demand {} processed successfully", demand_url),
+                        Err(e) => error!("Demand {} failed: {}", demand_url, e),
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to poll {}: {}", config.demand_list_url, e);
+                wait = next_backoff(wait, config.poll_interval, config.max_backoff);
+            }
+        }
+
+        if config.once {
+            return Ok(());
+        }
+        debug!("Sleeping {:?} before next poll", wait);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+fn next_backoff(current: Duration, poll_interval: Duration, max_backoff: Duration) -> Duration {
+    let doubled = if current < poll_interval { poll_interval } else { current * 2 };
+    if doubled > max_backoff { max_backoff } else { doubled }
+}
+
+/// Fetches every demand from the list endpoint, following `next` links when
+/// the API returns a paginated `{"results": [...]}` object.
+async fn fetch_demands(
+    client: &reqwest::Client,
+    list_url: &str,
+) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    let mut demands = Vec::new();
+    let mut next_url = Some(list_url.to_string());
+
+    while let Some(url) = next_url.take() {
+        debug!("Fetching demand list: {}", url);
+        let body: Value = client.get(&url).send().await?.error_for_status()?.json().await?;
+        match body {
+            Value::Array(items) => demands.extend(items),
+            Value::Object(mut page) => {
+                if let Some(Value::Array(items)) = page.remove("results") {
+                    demands.extend(items);
+                } else {
+                    return Err(format!("Unexpected demand list format from {}", url).into());
+                }
+                next_url = page.get("next").and_then(|v| v.as_str()).map(String::from);
+            }
+            _ => return Err(format!("Unexpected demand list format from {}", url).into()),
+        }
+    }
+
+    Ok(demands)
+}
+
+fn is_addressed_to(demand: &Value, brewer_name: &str) -> bool {
+    demand
+        .get(DBP_BREWER_INFO)
+        .and_then(|v| v.get(SC_NAME))
+        .and_then(|v| v.as_str())
+        == Some(brewer_name)
+}
+
+fn is_pending(demand: &Value) -> bool {
+    match demand.get(DBP_BREWING_STATUS) {
+        None | Some(Value::Null) => true,
+        Some(v) => v.as_str() == Some(DemandState::Pending.as_str()),
+    }
+}
+
+/// Re-reads the demand and marks it accepted, returning the copy it was
+/// claimed against, or `None` if another worker got there first. The state
+/// is read from the normalized copy, as when listing. The PATCH
+/// carries the ETag of the fresh copy so that a concurrent claim by another
+/// worker fails with 412 instead of both workers brewing the same demand.
+async fn claim_demand(
+    client: &reqwest::Client,
+    resolver: &RefResolver,
+    demand_url: &str,
+) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+    let resp = client.get(demand_url).send().await?.error_for_status()?;
+    let etag = resp.headers().get(header::ETAG).cloned();
+    let current: Value = resp.json().await?;
+    let normalized = json_ld_context::normalize(&current, resolver).await.unwrap_or_else(|e| {
+        warn!("Cannot process @context of demand {}: {}", demand_url, e);
+        current.clone()
+    });
+    if !is_pending(&normalized) {
+        return Ok(None);
+    }
+
+    let mut req = client
+        .patch(demand_url)
+        .json(&json!({ DBP_BREWING_STATUS: DemandState::Accepted.as_str() }));
+    if let Some(etag) = etag {
+        req = req.header(header::IF_MATCH, etag);
+    }
+    let resp = req.send().await?;
+    match resp.status() {
+        s if s.is_success() => Ok(Some(current)),
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => Ok(None),
+        s => Err(format!("Unexpected status {} while claiming demand", s).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header as header_is, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const DEMAND_PATH: &str = "/api/v0/brewing_demands/1/";
+
+    fn demand(url: &str, status: &str) -> Value {
+        json!({
+            "@id": url,
+            DBP_BREWER_INFO: { SC_NAME: "dbpBrewerTemplate" },
+            DBP_BREWING_STATUS: status,
+        })
+    }
+
+    fn offline() -> RefResolver {
+        RefResolver::new(None, None, true)
+    }
+
+    async fn serve_demand(server: &MockServer, status: &str) -> String {
+        let url = format!("{}{}", server.uri(), DEMAND_PATH);
+        Mock::given(method("GET"))
+            .and(path(DEMAND_PATH))
+            .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"v2\"").set_body_json(demand(&url, status)))
+            .expect(1)
+            .mount(server)
+            .await;
+        url
+    }
+
+    #[tokio::test]
+    async fn claims_a_pending_demand_with_its_etag() {
+        let server = MockServer::start().await;
+        let url = serve_demand(&server, "pending").await;
+        Mock::given(method("PATCH"))
+            .and(path(DEMAND_PATH))
+            .and(header_is("If-Match", "\"v2\""))
+            .and(body_json(json!({ DBP_BREWING_STATUS: "accepted" })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let claimed = claim_demand(&reqwest::Client::new(), &offline(), &url).await.unwrap();
+        assert_eq!(claimed, Some(demand(&url, "pending")));
+    }
+
+    #[tokio::test]
+    async fn loses_the_race_on_precondition_failed() {
+        let server = MockServer::start().await;
+        let url = serve_demand(&server, "pending").await;
+        Mock::given(method("PATCH"))
+            .and(path(DEMAND_PATH))
+            .respond_with(ResponseTemplate::new(412))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(claim_demand(&reqwest::Client::new(), &offline(), &url).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn skips_a_demand_accepted_since_listing() {
+        let server = MockServer::start().await;
+        let url = serve_demand(&server, "accepted").await;
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        assert_eq!(claim_demand(&reqwest::Client::new(), &offline(), &url).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reads_the_state_under_any_prefix() {
+        let server = MockServer::start().await;
+        let url = format!("{}{}", server.uri(), DEMAND_PATH);
+        Mock::given(method("GET"))
+            .and(path(DEMAND_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "@context": { "d": json_ld_context::DBP_IRI },
+                "@id": url,
+                "d:brewingStatus": "running",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        assert_eq!(claim_demand(&reqwest::Client::new(), &offline(), &url).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_unexpected_claim_status() {
+        let server = MockServer::start().await;
+        let url = serve_demand(&server, "pending").await;
+        Mock::given(method("PATCH")).respond_with(ResponseTemplate::new(500)).mount(&server).await;
+
+        assert!(claim_demand(&reqwest::Client::new(), &offline(), &url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn follows_demand_list_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/page2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "results": [{ "@id": "b" }], "next": null })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/list"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                json!({ "results": [{ "@id": "a" }], "next": format!("{}/page2", server.uri()) }),
+            ))
+            .mount(&server)
+            .await;
+
+        let demands = fetch_demands(&reqwest::Client::new(), &format!("{}/list", server.uri())).await.unwrap();
+        assert_eq!(demands, vec![json!({ "@id": "a" }), json!({ "@id": "b" })]);
+    }
+
+    #[tokio::test]
+    async fn polling_errors_are_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(503)).mount(&server).await;
+
+        assert!(fetch_demands(&reqwest::Client::new(), &server.uri()).await.is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let poll = Duration::from_secs(1);
+        let max = Duration::from_secs(8);
+        let mut wait = poll;
+        let mut waits = Vec::new();
+        for _ in 0..5 {
+            wait = next_backoff(wait, poll, max);
+            waits.push(wait.as_secs());
+        }
+        assert_eq!(waits, vec![2, 4, 8, 8, 8]);
+        assert_eq!(next_backoff(Duration::ZERO, poll, max), poll);
+    }
+}